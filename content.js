@@ -18,6 +18,7 @@ const sketchSystemsSuccessPaneId = "sketch-systems-success-message";
 const errorDivId = "sketch-systems-error-pane";
 const mainEditorContainerId = "main-editor-container";
 const jsEditorContainerId = "js-editor-container";
+const xstateVersionSelectId = "sketch-systems-xstate-version";
 
 let header = document.querySelector("header");
 const headerHeight = header.clientHeight;
@@ -218,20 +219,36 @@ function updateXstateEditor() {
   jsEditor.setValue(getFormattedJsCode(), 1);
   const jsInputStr = jsEditor.getValue();
 
-  let machineConfigObj = parse(inputStr.trim());
+  // v5 wants guards under `guard`, as objects
+  const guardStyle =
+    document.getElementById(xstateVersionSelectId).value === "v5"
+      ? "objects"
+      : "combined";
+
+  // warnings are not part of the machine config
+  let { warnings = [], ...machineConfigObj } = parse(inputStr.trim(), {
+    guardStyle,
+    // so tabs in the sketch indent as far as they do on screen
+    tabWidth: editor.getSession().getTabSize(),
+  });
 
   machineConfigObj = transformEventsArrayToObjStructure(machineConfigObj);
 
   console.log({ machineConfigObj });
   if (machineConfigObj.error) {
     console.error("Error parsing string", machineConfigObj.error);
-    showError({
-      message: machineConfigObj.error.message,
-      token: machineConfigObj.token,
-    });
+    showError(
+      {
+        message: machineConfigObj.error.message,
+        token: machineConfigObj.token,
+      },
+      machineConfigObj.errors,
+      warnings
+    );
   } else {
     clearErrorPane();
     showSuccessMessagePane();
+    editor.getSession().setAnnotations(annotations(warnings));
     const xstateEditor = ace.edit("brace-editor");
     const outputText = `const machineConfig = ${JSON.stringify(
       machineConfigObj,
@@ -350,6 +367,23 @@ function EditorHeightAdjuster() {
   return inputEl;
 }
 
+// which version of xstate the machine config is written for
+function XstateVersionSelect() {
+  return Element(
+    "label",
+    {
+      style: rangeInputLabelStyle,
+    },
+    [
+      "xstate",
+      Element("select", { id: xstateVersionSelectId }, [
+        Element("option", { value: "v4" }, ["v4"]),
+        Element("option", { value: "v5" }, ["v5"]),
+      ]),
+    ]
+  );
+}
+
 export function Toolbar() {
   const headerStyles = {
     background: "rgb(101, 101, 101)",
@@ -365,6 +399,7 @@ export function Toolbar() {
     FormatButton(),
     WidthInput(),
     EditorHeightAdjuster(),
+    XstateVersionSelect(),
   ]);
 }
 
@@ -493,7 +528,20 @@ function hideSuccessMessagePane() {
   successMessagePane.style.display = "none";
 }
 
-function showError(error) {
+// warnings carry zero based positions, like the ace gutter
+// errors and warnings from the parser. Their severity is "error" or
+// "warning", which is also what ace calls them.
+function annotations(problems) {
+  return problems.map((problem) => ({
+    row: problem.pos.line_number,
+    column: problem.pos.col,
+    text: problem.message,
+    type: problem.severity,
+  }));
+}
+
+// `errors` has every error the parser found, `error` is the first of them
+function showError(error, errors = [], warnings = []) {
   hideSuccessMessagePane();
   const errorPane = document.getElementById("sketch-systems-error-pane");
 
@@ -503,15 +551,25 @@ function showError(error) {
     errorPane.innerHTML += `<div>Line no: ${error.token.line}, Column no: ${error.token.col}</div>`;
   }
 
-  // show the error in ace editor's gutter
-  const a = editor.getSession().setAnnotations([
-    {
-      row: error.token.line - 1,
-      column: error.token.col - 1,
-      text: error.message,
-      type: "error",
-    },
-  ]);
+  if (errors.length > 1) {
+    errorPane.innerHTML += `<div>and ${errors.length - 1} more, see the gutter</div>`;
+  }
+
+  // show the errors in ace editor's gutter
+  const errorAnnotations =
+    errors.length > 0
+      ? annotations(errors)
+      : [
+          {
+            row: error.token.line - 1,
+            column: error.token.col - 1,
+            text: error.message,
+            type: "error",
+          },
+        ];
+  editor
+    .getSession()
+    .setAnnotations([...errorAnnotations, ...annotations(warnings)]);
 }
 
 function findByText(elName, text) {
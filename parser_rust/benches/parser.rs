@@ -0,0 +1,59 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use parser_rust::parser::tokenizer::tokenize;
+use parser_rust::parser::Parser;
+
+// Generates a sketch with roughly `lines` lines. Every state has a few
+// transitions (with conditions, actions and comments) and a couple of nested
+// states, which is what big hand written sketches look like.
+fn generate_sketch(lines: usize) -> String {
+    let mut sketch = String::from("machine\n");
+    let mut i = 0;
+
+    while sketch.lines().count() < lines {
+        sketch.push_str(&format!(
+            "  state{i}&
+    % handles the events of state{i}
+    NEXT_{i} -> state{next} > logNext > #machine.counter
+    BACK_{i} -> state{i}; canGoBack
+    -> #machine.state{i}.child{i}; isReady
+    child{i}
+      DONE -> child{i}_done > cleanUp
+    child{i}_done$
+",
+            i = i,
+            next = i + 1,
+        ));
+        i += 1;
+    }
+
+    sketch
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+
+    for lines in [200, 2000].iter() {
+        let sketch = generate_sketch(*lines);
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &sketch, |b, sketch| {
+            b.iter(|| tokenize(black_box(sketch)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for lines in [200, 2000].iter() {
+        let sketch = generate_sketch(*lines);
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &sketch, |b, sketch| {
+            b.iter(|| Parser::new().parse_with_recovery(black_box(sketch)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_parse);
+criterion_main!(benches);
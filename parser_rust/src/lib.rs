@@ -1,4 +1,4 @@
-mod parser;
+pub mod parser;
 
 #[macro_use]
 extern crate serde_derive;
@@ -18,14 +18,163 @@ extern "C" {
     fn alert(s: &str);
 }
 
+// The shape content.js expects when parsing fails. `error` and `token` are
+// about the first error. `token` carries one based line and column numbers,
+// which is what the ace editor gutter wants. `errors` has every error found.
+// `ast` is the partial tree parsed around the errors, if there is one.
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a ParseError<'a>,
+    ast: Option<&'a StateNode<'a>>,
+    token: ErrorLocation,
+    errors: &'a [ParseError<'a>],
+    warnings: &'a [ParseError<'a>],
+}
+
+// When parsing succeeds content.js gets the machine config itself, with the
+// warnings next to the states
+#[derive(Serialize)]
+struct SuccessResponse<'a> {
+    #[serde(flatten)]
+    ast: &'a StateNode<'a>,
+    warnings: &'a [ParseError<'a>],
+}
+
+#[derive(Serialize)]
+struct ErrorLocation {
+    line: usize,
+    col: usize,
+}
+
+// When something goes wrong outside of the sketch, e.g. the response can't be
+// turned into a js value, content.js gets this instead. That way it can show
+// what went wrong like any other error.
+#[derive(Serialize)]
+struct MessageResponse {
+    error: Message,
+    token: ErrorLocation,
+}
+
+#[derive(Serialize)]
+struct Message {
+    message: String,
+}
+
+// What content.js can pass as the second argument of parse. Every field is
+// optional, and so is the argument itself.
+// E.g. parse(sketch, { guardStyle: "objects", tabWidth: 2 })
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct JsOptions {
+    guard_style: GuardStyle,
+    // the editor's tab size, so tabs indent the same here as on screen
+    tab_width: Option<usize>,
+}
+
+impl JsOptions {
+    fn parser_options(self, lenient: bool) -> ParserOptions {
+        ParserOptions {
+            lenient,
+            tab_width: self.tab_width.unwrap_or(tokenizer::DEFAULT_TAB_WIDTH),
+            guard_style: self.guard_style,
+        }
+    }
+}
+
+fn message_response(message: String) -> JsValue {
+    to_js_value(&MessageResponse {
+        error: Message { message },
+        token: ErrorLocation { line: 1, col: 1 },
+    })
+}
+
+fn to_js_value<T: serde::Serialize>(value: &T) -> JsValue {
+    // json_compatible serializes maps as plain objects instead of es6 Maps
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+
+    value.serialize(&serializer).unwrap_or_else(|error| {
+        let response = MessageResponse {
+            error: Message {
+                message: error.to_string(),
+            },
+            token: ErrorLocation { line: 1, col: 1 },
+        };
+        // rust tip: a serde_wasm_bindgen::Error is a js Error underneath, so
+        // it can be handed back as is if even this fails
+        serde::Serialize::serialize(&response, &serializer).unwrap_or_else(JsValue::from)
+    })
+}
+
+// `options` is a JsOptions object, or undefined for the defaults
 #[wasm_bindgen]
-pub fn parse(input: &str) -> JsValue {
-    let mut parser = Parser::new();
+pub fn parse(input: &str, options: JsValue) -> JsValue {
+    parse_with_js_options(input, options, false)
+}
+
+// Like parse, but input left over after the root state is only a warning.
+// Handy for parsing while the user is still typing.
+#[wasm_bindgen]
+pub fn parse_lenient(input: &str, options: JsValue) -> JsValue {
+    parse_with_js_options(input, options, true)
+}
+
+fn parse_with_js_options(input: &str, options: JsValue, lenient: bool) -> JsValue {
+    // rust tip: undefined and null both become None
+    match serde_wasm_bindgen::from_value::<Option<JsOptions>>(options) {
+        Ok(options) => {
+            parse_with_options(input, options.unwrap_or_default().parser_options(lenient))
+        }
+        Err(error) => message_response(format!("invalid parser options: {}", error)),
+    }
+}
+
+fn parse_with_options(input: &str, options: ParserOptions) -> JsValue {
+    let mut parser = Parser::with_options(options);
+
+    let ParseOutput {
+        ast,
+        errors,
+        warnings,
+    } = parser.parse_with_recovery(input);
+
+    match (ast, errors.first()) {
+        (Some(ast), None) => to_js_value(&SuccessResponse {
+            ast: &ast,
+            warnings: &warnings,
+        }),
+        (ast, Some(error)) => to_js_value(&ErrorResponse {
+            error,
+            ast: ast.as_ref(),
+            token: ErrorLocation {
+                line: error.pos.line_number + 1,
+                col: error.pos.col + 1,
+            },
+            errors: &errors,
+            warnings: &warnings,
+        }),
+        (None, None) => unreachable!("parse_with_recovery reports an error when there is no ast"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_from_js() {
+        let options: JsOptions =
+            serde_json::from_value(serde_json::json!({ "guardStyle": "objects", "tabWidth": 2 }))
+                .unwrap();
+        let options = options.parser_options(true);
+        assert_eq!(options.guard_style, GuardStyle::Objects);
+        assert_eq!(options.tab_width, 2);
 
-    let ast = parser.parse(input);
+        // everything is optional
+        let options: JsOptions = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(options.parser_options(false), ParserOptions::default());
 
-    match ast {
-        Ok(ast) => JsValue::from_serde(&ast).unwrap(),
-        Err(error_str) => JsValue::from_serde(error_str).unwrap(),
+        assert!(
+            serde_json::from_value::<JsOptions>(serde_json::json!({ "guardStyle": "v5" })).is_err()
+        );
     }
 }
@@ -1,15 +1,29 @@
-use serde::ser::{Serialize, Serializer};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::cell::RefCell;
 
-mod tokenizer;
+mod call;
+mod cursor;
+mod error;
+mod guard;
+mod resolve;
+pub mod tokenizer;
+mod validate;
+pub use call::{Call, Literal};
+use cursor::TokenCursor;
+pub use error::{ParseError, Severity};
+pub use guard::{Condition, Guard, GuardStyle};
 use tokenizer::*;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[allow(clippy::enum_variant_names)]
 enum StateType {
+    #[default]
     AtomicState,
     CompoundState,
     FinalState,
     ParallelState,
+    History,
 }
 
 // we want to convert the StateType to strings which xstate understands
@@ -24,23 +38,214 @@ impl Serialize for StateType {
             StateType::CompoundState => "compound",
             StateType::FinalState => "final",
             StateType::ParallelState => "parallel",
+            StateType::History => "history",
         })
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+// how long `after` waits. A number of milliseconds, or the name of a delay
+// which is set up in the machine options.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Delay<'a> {
+    Millis(u64),
+    Named(&'a str),
+}
+
+impl<'a> Delay<'a> {
+    // `500ms`, `2s`, `1.5m` or `500` (milliseconds) for numbers. Anything
+    // starting with a letter or `_` is a name. Everything else, like `.5s` or
+    // `#foo`, is a mistake. So are numbers which are less than a millisecond
+    // once rounded, or more than js can count exactly.
+    fn parse(text: &'a str) -> Option<Delay<'a>> {
+        if text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return Some(Delay::Named(text));
+        }
+        if !text.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let unit_start = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let number: f64 = text[..unit_start].parse().ok()?;
+        let millis_per_unit = match &text[unit_start..] {
+            "" | "ms" => 1.0,
+            "s" => 1000.0,
+            "m" => 60_000.0,
+            _ => return None,
+        };
+
+        let millis = (number * millis_per_unit).round();
+        if millis < 1.0 || millis > call::MAX_SAFE_INTEGER as f64 {
+            return None;
+        }
+
+        Some(Delay::Millis(millis as u64))
+    }
+
+    // the key in xstate's `after` map
+    fn key(&self) -> String {
+        match self {
+            Delay::Millis(millis) => millis.to_string(),
+            Delay::Named(name) => name.to_string(),
+        }
+    }
+}
+
+// A transition written with `~>` (internal) or `=>` (external). xstate v5
+// dropped `internal` for `reenter`, which means the opposite, so the guard
+// style picks the key along with the guards. It serializes as a map with a
+// single entry, which transitions flatten into themselves, e.g.
+// { internal: true } or { reenter: false }.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Reentry {
+    internal: bool,
+    style: GuardStyle,
+}
+
+impl Serialize for Reentry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self.style {
+            GuardStyle::Combined => map.serialize_entry("internal", &self.internal)?,
+            GuardStyle::Objects => map.serialize_entry("reenter", &!self.internal)?,
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
 pub struct TransitionNode<'a> {
     event: &'a str,
-    target: &'a str,
-    // Use a method to decide whether the field should be skipped.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    cond: Option<&'a str>,
+    // more than one target only makes sense for states in different regions
+    // of a parallel state, e.g. `GO -> #m.a.x, #m.b.y`
+    // empty for a transition which only runs actions, e.g. `TICK > count`
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_targets"
+    )]
+    target: Vec<&'a str>,
+    // Some when the transition is written with `~>` or `=>`. Otherwise xstate
+    // picks, based on the target.
+    #[serde(flatten)]
+    internal: Option<Reentry>,
+    // `cond` or `guard`, depending on the guard style
+    #[serde(flatten)]
+    cond: Option<Condition<'a>>,
     // Use a method to decide whether the field should be skipped.
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    actions: Vec<&'a str>,
+    actions: Vec<Call<'a>>,
+    // where the transition starts in the sketch, for reporting mistakes
+    // found after parsing. xstate doesn't need it.
+    #[serde(skip)]
+    pos: Position,
+    // one for each target
+    #[serde(skip)]
+    target_pos: Vec<Position>,
+    // only for `after` transitions, which are kept in StateNode::after
+    #[serde(skip)]
+    delay: Option<Delay<'a>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+// A transition the way xstate writes it when the event is already known from
+// where the transition sits, e.g. in `after` or `onDone`
+#[derive(Serialize)]
+struct TransitionConfig<'b> {
+    #[serde(
+        skip_serializing_if = "<[_]>::is_empty",
+        serialize_with = "serialize_targets"
+    )]
+    target: &'b [&'b str],
+    #[serde(flatten)]
+    internal: Option<Reentry>,
+    #[serde(flatten)]
+    cond: Option<&'b Condition<'b>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    actions: &'b [Call<'b>],
+}
+
+impl<'b> TransitionConfig<'b> {
+    fn new(transition: &'b TransitionNode) -> Self {
+        TransitionConfig {
+            target: &transition.target,
+            internal: transition.internal,
+            cond: transition.cond.as_ref(),
+            actions: &transition.actions,
+        }
+    }
+}
+
+// a single target is written as a string, like everyone writes it by hand.
+// xstate takes an array for several.
+fn serialize_targets<S>(target: &[&str], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match target {
+        [target] => serializer.serialize_str(target),
+        targets => serializer.collect_seq(targets),
+    }
+}
+
+// xstate wants `after` as a map from the delay to the transitions taken after
+// it, e.g. { "500": [{ target: "idle" }] }
+fn serialize_after<S>(after: &[TransitionNode], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut by_delay: IndexMap<String, Vec<TransitionConfig>> = IndexMap::new();
+    for transition in after {
+        let key = transition
+            .delay
+            .as_ref()
+            .map(Delay::key)
+            .unwrap_or_default();
+        by_delay
+            .entry(key)
+            .or_default()
+            .push(TransitionConfig::new(transition));
+    }
+
+    serializer.collect_map(by_delay)
+}
+
+fn serialize_transition_configs<S>(
+    transitions: &[TransitionNode],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(transitions.iter().map(TransitionConfig::new))
+}
+
+// a service started when the state is entered, e.g. a promise which fetches
+// some data. `done` and `error` transitions in its block become `onDone` and
+// `onError`.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvokeNode<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    src: &'a str,
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_transition_configs"
+    )]
+    on_done: Vec<TransitionNode<'a>>,
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_transition_configs"
+    )]
+    on_error: Vec<TransitionNode<'a>>,
+    #[serde(skip)]
+    pos: Position,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
 pub struct StateNode<'a> {
     id: &'a str,
     // rust tip: We can't use the property name "type" because it's a rust
@@ -52,6 +257,26 @@ pub struct StateNode<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     initial: Option<&'a str>,
     is_initial: bool,
+    // only for history states. `target` is the state to go to when there is
+    // no history yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history: Option<HistoryType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+    #[serde(skip)]
+    target_pos: Position,
+    // actions run when the state is entered and exited, in the order they
+    // were written
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entry: Vec<Call<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exit: Vec<Call<'a>>,
+    // long running things which go on for as long as the state is active,
+    // like a ticking timer or a beeping alarm
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    activities: Vec<Call<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    invoke: Vec<InvokeNode<'a>>,
     // xstate has a representation of events as
     // {
     // on: [
@@ -67,13 +292,64 @@ pub struct StateNode<'a> {
     // convert most events to { on: { 'click': 'go_to_state_1' }} form, because
     // that's what most people want. Or not.
     on: Vec<TransitionNode<'a>>,
-    states: HashMap<&'a str, StateNode<'a>>,
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_after"
+    )]
+    after: Vec<TransitionNode<'a>>,
+    // `done -> target` in a compound or parallel state. Taken when the state
+    // reaches one of its final states.
+    #[serde(
+        rename(serialize = "onDone"),
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_transition_configs"
+    )]
+    on_done: Vec<TransitionNode<'a>>,
+    // IndexMap instead of HashMap so that the states come out in the order
+    // they were written, and the json is the same from one run to the next
+    states: IndexMap<&'a str, StateNode<'a>>,
+    // position of the state's name in the sketch
+    #[serde(skip)]
+    pos: Position,
+    // child states with the same name as an earlier sibling. `states` can
+    // only hold one of them, so the rest are kept here until validation
+    // reports them.
+    #[serde(skip)]
+    duplicate_states: Vec<StateNode<'a>>,
+    // the `&`, `$` and `*` tokens after the state's name, so that validation
+    // can point at them
+    #[serde(skip)]
+    markers: Vec<Token<'a>>,
+}
+
+impl<'a> StateNode<'a> {
+    // every transition going out of this state, wherever it is written
+    fn all_transitions(&self) -> impl Iterator<Item = &TransitionNode<'a>> {
+        let invoke_transitions = self
+            .invoke
+            .iter()
+            .flat_map(|i| i.on_done.iter().chain(i.on_error.iter()));
+
+        self.on
+            .iter()
+            .chain(self.after.iter())
+            .chain(self.on_done.iter())
+            .chain(invoke_transitions)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum TransitionOrState<'a> {
-    State(StateNode<'a>),
-    Transition(TransitionNode<'a>),
+    // boxed because a state is a lot bigger than everything else here
+    State(Box<StateNode<'a>>),
+    // a line can hold several transitions, e.g. `A | B -> x`
+    Transitions(Vec<TransitionNode<'a>>),
+    Entry(Vec<Call<'a>>),
+    Exit(Vec<Call<'a>>),
+    Activities(Vec<Call<'a>>),
+    Invoke(InvokeNode<'a>),
+    // a line which could not be parsed. The error is already recorded.
+    Skipped,
 }
 // TODO: This return value is not enough. We need to consume the token, which
 // means updating the offset. Each parser can change the offset by different
@@ -137,8 +413,57 @@ enum TransitionOrState<'a> {
 // And only parser combinators worry about backtracking, which involves putting
 // the offset/index back to some previous position.
 
+// What Parser::parse_with_recovery returns. The parser does not stop at the
+// first error. It skips the broken line (and anything nested under it) and
+// carries on, so `errors` can have many entries and `ast` is whatever could
+// be parsed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct ParseOutput<'a> {
+    pub ast: Option<StateNode<'a>>,
+    pub errors: Vec<ParseError<'a>>,
+    pub warnings: Vec<ParseError<'a>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParserOptions {
+    // Report input left over after the root state as warnings instead of
+    // errors. Handy while the user is still typing.
+    pub lenient: bool,
+    // how many columns a tab in the indentation counts for
+    pub tab_width: usize,
+    // whether guards are written for xstate v4 (`cond`) or v5 (`guard`)
+    pub guard_style: GuardStyle,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            lenient: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            guard_style: GuardStyle::default(),
+        }
+    }
+}
+
 pub struct Parser<'a> {
-    tokens: Vec<Token<'a>>,
+    options: ParserOptions,
+    tokens: TokenCursor<'a>,
+    // false when we stop at the first error, like Parser::parse does
+    recovering: bool,
+    input_str: &'a str,
+    end_of_input: Position,
+    errors: RefCell<Vec<ParseError<'a>>>,
+    warnings: RefCell<Vec<ParseError<'a>>>,
+    // The furthest offset at which a token did not match, along with all the
+    // token kinds which were tried there. Parsers backtrack a lot, so the
+    // furthest failure is usually the one closest to the actual mistake.
+    furthest_failure: RefCell<(usize, Vec<TokenKind>)>,
+}
+
+impl<'a> Default for Parser<'a> {
+    fn default() -> Self {
+        Parser::new()
+    }
 }
 
 // looks like i can't write this method zero_or_one in rust
@@ -177,18 +502,23 @@ where
         parsed_values.push(v);
     }
 
-    if parsed_values.len() > 0 {
-        return Some((new_offset, parsed_values));
+    if !parsed_values.is_empty() {
+        Some((new_offset, parsed_values))
     } else {
-        return None;
+        None
     }
 }
 
 fn get_state_type(
+    is_history_state: bool,
     is_parallel_state: bool,
     is_final_state: bool,
     sub_states_count: usize,
 ) -> StateType {
+    if is_history_state {
+        return StateType::History;
+    }
+
     if is_parallel_state {
         return StateType::ParallelState;
     }
@@ -201,19 +531,27 @@ fn get_state_type(
         return StateType::CompoundState;
     }
 
-    return StateType::AtomicState;
+    StateType::AtomicState
 }
 
-fn get_initial_state<'a>(sub_states: &Vec<(&'a str, StateNode<'a>)>) -> Option<&'a str> {
-    if sub_states.len() == 0 {
+fn get_initial_state<'a>(
+    typ: &StateType,
+    sub_states: &[(&'a str, StateNode<'a>)],
+) -> Option<&'a str> {
+    // all the regions of a parallel state are active at once. xstate refuses
+    // parallel states with an initial state.
+    if sub_states.is_empty() || *typ == StateType::ParallelState {
         return None;
     }
 
-    if let Some((initial_sub_state, _)) = sub_states.iter().find(|(_, s)| s.is_initial == true) {
-        return Some(initial_sub_state);
+    if let Some((initial_sub_state, _)) = sub_states.iter().find(|(_, s)| s.is_initial) {
+        Some(initial_sub_state)
     } else {
-        let (initial_sub_state, _) = sub_states[0];
-        return Some(initial_sub_state);
+        // a history state is not a state the machine can be in
+        sub_states
+            .iter()
+            .find(|(_, s)| s.typ != StateType::History)
+            .map(|(initial_sub_state, _)| *initial_sub_state)
     }
 }
 
@@ -236,15 +574,166 @@ impl<'a> Parser<'a> {
     // 1. Store the input_str inside the parser
     // 2. Won't have to create a new instance of Parser for every new parse
     pub fn new() -> Parser<'a> {
-        Parser { tokens: vec![] }
+        Parser::with_options(ParserOptions::default())
     }
 
-    fn get_token_at(&self, offset: usize) -> Option<&Token<'a>> {
-        if offset < self.tokens.len() {
-            return Some(&self.tokens[offset]);
+    pub fn with_options(options: ParserOptions) -> Parser<'a> {
+        Parser {
+            options,
+            tokens: TokenCursor::new(Lexer::new("")),
+            recovering: true,
+            input_str: "",
+            end_of_input: Position::default(),
+            errors: RefCell::new(vec![]),
+            warnings: RefCell::new(vec![]),
+            furthest_failure: RefCell::new((0, vec![])),
         }
+    }
 
-        None
+    fn get_token_at(&self, offset: usize) -> Option<Token<'a>> {
+        self.tokens.get(offset)
+    }
+
+    // Every parser which fails to match a token calls this. We only care
+    // about failures at the furthest offset, so the error can list everything
+    // which would have been accepted there.
+    fn expected(&self, offset: usize, kind: TokenKind) {
+        let mut furthest_failure = self.furthest_failure.borrow_mut();
+
+        if offset > furthest_failure.0 {
+            *furthest_failure = (offset, vec![kind]);
+        } else if offset == furthest_failure.0 && !furthest_failure.1.contains(&kind) {
+            furthest_failure.1.push(kind);
+        }
+    }
+
+    // forget failures from before offset. Called before parsing each line so
+    // that an error only lists what was expected on that line.
+    fn reset_furthest_failure(&self, offset: usize) {
+        *self.furthest_failure.borrow_mut() = (offset, vec![]);
+    }
+
+    // line_offset is the offset of the first token of the line which failed
+    fn error_at_furthest_failure(&self, line_offset: usize) -> ParseError<'a> {
+        let (offset, mut expected) = self.furthest_failure.borrow().clone();
+        expected.sort();
+
+        match self.get_token_at(offset) {
+            None => ParseError::unexpected_end(self.end_of_input.clone(), "end of input", expected),
+            Some(token) if offset > line_offset && !self.continues_line(offset) => {
+                // the line ended before we found what we wanted. Point right
+                // after the last token of that line instead of at whatever
+                // comes on the next line
+                // offset > line_offset, so there is a token before it
+                let last = self.get_token_at(offset - 1).unwrap().pos;
+                let pos = Position {
+                    line_number: last.line_number,
+                    col: last.col + self.input_str[last.start..last.end].chars().count(),
+                    start: last.end,
+                    end: last.end,
+                };
+
+                if token.typ == TokenType::Dedent && token.pos == self.end_of_input {
+                    ParseError::unexpected_end(pos, "end of input", expected)
+                } else {
+                    ParseError::unexpected_end(pos, "end of line", expected)
+                }
+            }
+            Some(token) => ParseError::unexpected_token(token, expected),
+        }
+    }
+
+    // true if the token at offset is on the same line as the token before it.
+    // Indent and dedent tokens always start a new line.
+    fn continues_line(&self, offset: usize) -> bool {
+        if offset == 0 {
+            return false;
+        }
+
+        match (self.get_token_at(offset - 1), self.get_token_at(offset)) {
+            (Some(prev), Some(token)) => {
+                token.typ != TokenType::Indent
+                    && token.typ != TokenType::Dedent
+                    && token.pos.line_number == prev.pos.line_number
+            }
+            _ => false,
+        }
+    }
+
+    // Records an error for the line starting at offset and skips past it.
+    // Returns None if there is nothing to skip, i.e. we are at the end of the
+    // current block.
+    // When we are not recovering, the first error stops every parser above
+    // it, and nothing after it gets tokenized.
+    fn recover(&self, offset: usize) -> Option<usize> {
+        if !self.recovering && !self.errors.borrow().is_empty() {
+            return None;
+        }
+
+        let token = self.get_token_at(offset)?;
+        if token.typ == TokenType::Dedent {
+            return None;
+        }
+
+        self.errors
+            .borrow_mut()
+            .push(self.error_at_furthest_failure(offset));
+
+        if !self.recovering {
+            return None;
+        }
+
+        Some(self.skip_line(offset))
+    }
+
+    // Reports everything after the root state, one diagnostic per line
+    fn trailing_input(&self, mut offset: usize) {
+        while self.get_token_at(offset).is_some() {
+            if !self.recovering && !self.errors.borrow().is_empty() {
+                return;
+            }
+
+            self.reset_furthest_failure(offset);
+            self.expected(offset, TokenKind::EndOfInput);
+            let error = self.error_at_furthest_failure(offset);
+
+            if self.options.lenient {
+                self.warnings.borrow_mut().push(error.into_warning());
+            } else {
+                self.errors.borrow_mut().push(error);
+            }
+
+            offset = self.skip_line(offset);
+        }
+    }
+
+    // skips the line starting at offset, along with any block indented
+    // under it
+    fn skip_line(&self, offset: usize) -> usize {
+        let mut offset = offset;
+        if self.get_token_at(offset).map(|t| t.typ) != Some(TokenType::Indent) {
+            offset += 1;
+            while self.continues_line(offset) {
+                offset += 1;
+            }
+        }
+
+        if let Some((new_offset, _)) = self.indent(offset) {
+            let mut depth = 1;
+            offset = new_offset;
+
+            while depth > 0 {
+                match self.get_token_at(offset).map(|t| t.typ) {
+                    Some(TokenType::Indent) => depth += 1,
+                    Some(TokenType::Dedent) => depth -= 1,
+                    Some(_) => {}
+                    None => break,
+                }
+                offset += 1;
+            }
+        }
+
+        offset
     }
 
     // This parser works for all parsers which want to compare token to
@@ -252,112 +741,353 @@ impl<'a> Parser<'a> {
     // It helps take care of some repeating things
     // 1. use get_token_at and pull value out of the Option returned by that method
     // 2. Wrap the values in Option enum
-    fn match_parser<T, F, F2>(&self, offset: usize, pred: F, get_val: F2) -> Option<(usize, T)>
+    // 3. Record the expected token kind if the match fails
+    fn match_parser<T, F>(&self, offset: usize, kind: TokenKind, get_val: F) -> Option<(usize, T)>
     where
-        F: Fn(&Token<'a>) -> bool,
-        F2: Fn(&Token<'a>) -> T,
+        F: Fn(&Token<'a>) -> T,
     {
         if let Some(token) = self.get_token_at(offset) {
-            // TODO: If i can change below if to `if let`, then i can pass
-            // whatever was found inside the enum which was matched and pass
-            // it on to get_val. Otherwise, get_val would again have to match
-            // token.typ to enum and send back and Option
-            // Maybe we can have get_val return an Option and always do
-            // get_val(token).unwrap()
-            if pred(token) {
-                return Some((offset + 1, get_val(token)));
+            if token.typ.kind() == kind {
+                return Some((offset + 1, get_val(&token)));
             }
         }
 
+        self.expected(offset, kind);
         None
     }
 
     fn identifier(&self, offset: usize) -> Option<(usize, &'a str)> {
-        if let Some(token) = self.get_token_at(offset) {
-            if let TokenType::Identifier(text) = token.typ {
-                return Some((offset + 1, text));
+        self.match_parser(offset, TokenKind::Identifier, |token| match token.typ {
+            TokenType::Identifier(text) => text,
+            _ => unreachable!(),
+        })
+    }
+
+    fn transition_arrow(&self, offset: usize) -> Option<(usize, bool)> {
+        self.match_parser(offset, TokenKind::TransitionArrow, |_| true)
+    }
+
+    // `->`, `~>` or `=>`, with whether the transition is internal. The
+    // explicit arrows are peeked at, so a missing arrow is reported as a
+    // missing `->`.
+    fn any_transition_arrow(&self, offset: usize) -> Option<(usize, Option<bool>)> {
+        match self.get_token_at(offset).map(|t| t.typ) {
+            Some(TokenType::InternalArrow) => Some((offset + 1, Some(true))),
+            Some(TokenType::ExternalArrow) => Some((offset + 1, Some(false))),
+            _ => {
+                let (offset, _) = self.transition_arrow(offset)?;
+                Some((offset, None))
             }
         }
+    }
 
-        None
+    // The guard after `;`. Precedence climbs the usual way:
+    //   or  := and (`||` and)*
+    //   and := not (`&&` not)*
+    //   not := `!` not | name | `(` or `)`
+    fn condition(&self, offset: usize) -> Option<(usize, Condition<'a>)> {
+        let (offset, guard) = self.or_guard(offset)?;
+
+        Some((
+            offset,
+            Condition {
+                guard,
+                style: self.options.guard_style,
+            },
+        ))
     }
 
-    fn transition_arrow(&self, offset: usize) -> Option<(usize, bool)> {
-        if let Some(token) = self.get_token_at(offset) {
-            if let TokenType::TransitionArrow = token.typ {
-                return Some((offset + 1, true));
-            }
+    fn or_guard(&self, offset: usize) -> Option<(usize, Guard<'a>)> {
+        self.guard_operands(offset, TokenKind::Or, Self::and_guard, Guard::Or)
+    }
+
+    fn and_guard(&self, offset: usize) -> Option<(usize, Guard<'a>)> {
+        self.guard_operands(offset, TokenKind::And, Self::not_guard, Guard::And)
+    }
+
+    // one or more operands separated by `operator`. A single operand is
+    // returned as it is, so `a` doesn't become an `and` of one guard.
+    fn guard_operands(
+        &self,
+        offset: usize,
+        operator: TokenKind,
+        operand: fn(&Self, usize) -> Option<(usize, Guard<'a>)>,
+        combine: fn(Vec<Guard<'a>>) -> Guard<'a>,
+    ) -> Option<(usize, Guard<'a>)> {
+        let (offset, first) = operand(self, offset)?;
+        let (offset, rest) = zero_or_more(offset, |offset| {
+            let (offset, _) = self.match_parser(offset, operator, |_| ())?;
+            operand(self, offset)
+        })
+        .unwrap_or((offset, vec![]));
+
+        if rest.is_empty() {
+            return Some((offset, first));
         }
 
-        None
+        let mut guards = vec![first];
+        guards.extend(rest);
+        Some((offset, combine(guards)))
     }
 
-    fn condition(&self, offset: usize) -> Option<(usize, &'a str)> {
-        if let Some(token) = self.get_token_at(offset) {
-            if let TokenType::Condition(cond_str) = token.typ {
-                return Some((offset + 1, cond_str));
+    // `!` and `(` are peeked at instead of matched, so a missing guard is
+    // reported as a missing condition rather than a list of operators
+    fn not_guard(&self, offset: usize) -> Option<(usize, Guard<'a>)> {
+        match self.get_token_at(offset).map(|t| t.typ) {
+            Some(TokenType::Not) => {
+                let (offset, guard) = self.not_guard(offset + 1)?;
+                return Some((offset, Guard::Not(Box::new(guard))));
             }
+            Some(TokenType::LeftParen) => {
+                let (offset, guard) = self.or_guard(offset + 1)?;
+                let (offset, _) = self.match_parser(offset, TokenKind::RightParen, |_| ())?;
+                return Some((offset, guard));
+            }
+            _ => {}
         }
 
-        None
+        let (offset, name) =
+            self.match_parser(offset, TokenKind::Condition, |token| match token.typ {
+                TokenType::Condition(name) => name,
+                _ => unreachable!(),
+            })?;
+        let (offset, call) = self.call(offset, name)?;
+        Some((offset, Guard::Named(call)))
     }
 
-    fn action(&self, offset: usize) -> Option<(usize, &'a str)> {
-        if let Some(token) = self.get_token_at(offset) {
-            if let TokenType::Action(cond_str) = token.typ {
-                return Some((offset + 1, cond_str));
-            }
+    fn action(&self, offset: usize) -> Option<(usize, Call<'a>)> {
+        let (offset, name) =
+            self.match_parser(offset, TokenKind::Action, |token| match token.typ {
+                TokenType::Action(action_str) => action_str,
+                _ => unreachable!(),
+            })?;
+        self.call(offset, name)
+    }
+
+    // an action or condition name with its arguments, if it has any
+    fn call(&self, offset: usize, name: &'a str) -> Option<(usize, Call<'a>)> {
+        let (offset, params) =
+            zero_or_one(offset, |offset| self.arguments(offset)).unwrap_or((offset, vec![]));
+
+        Some((offset, Call { name, params }))
+    }
+
+    // `("admin", 2, true)`. The `(` is peeked at, because most names have
+    // no arguments and errors shouldn't suggest adding some.
+    fn arguments(&self, offset: usize) -> Option<(usize, Vec<Literal<'a>>)> {
+        if self.get_token_at(offset).map(|t| t.typ) != Some(TokenType::LeftParen) {
+            return None;
         }
+        let offset = offset + 1;
 
-        None
+        let (offset, params) = zero_or_one(offset, |offset| {
+            let (offset, first) = self.literal(offset)?;
+            let (offset, rest) = zero_or_more(offset, |offset| {
+                let (offset, _) = self.match_parser(offset, TokenKind::Comma, |_| ())?;
+                self.literal(offset)
+            })
+            .unwrap_or((offset, vec![]));
+
+            let mut params = vec![first];
+            params.extend(rest);
+            Some((offset, params))
+        })
+        .unwrap_or((offset, vec![]));
+        let (offset, _) = self.match_parser(offset, TokenKind::RightParen, |_| ())?;
+
+        Some((offset, params))
+    }
+
+    fn literal(&self, offset: usize) -> Option<(usize, Literal<'a>)> {
+        match self.get_token_at(offset).map(|t| t.typ) {
+            Some(TokenType::String(text)) => Some((offset + 1, Literal::String(text))),
+            Some(TokenType::Identifier(text)) => Some((offset + 1, Literal::from_identifier(text))),
+            _ => {
+                self.expected(offset, TokenKind::Argument);
+                None
+            }
+        }
     }
 
     fn parallel_state(&self, offset: usize) -> Option<(usize, bool)> {
-        self.match_parser(
-            offset,
-            |token| token.typ == TokenType::ParallelState,
-            |_| true,
-        )
+        self.match_parser(offset, TokenKind::ParallelState, |_| true)
     }
 
     fn final_state(&self, offset: usize) -> Option<(usize, bool)> {
-        self.match_parser(offset, |token| token.typ == TokenType::FinalState, |_| true)
+        self.match_parser(offset, TokenKind::FinalState, |_| true)
     }
 
     fn initial_state(&self, offset: usize) -> Option<(usize, bool)> {
-        self.match_parser(
-            offset,
-            |token| token.typ == TokenType::InitialState,
-            |_| true,
-        )
+        self.match_parser(offset, TokenKind::InitialState, |_| true)
+    }
+
+    fn entry(&self, offset: usize) -> Option<(usize, bool)> {
+        self.match_parser(offset, TokenKind::Entry, |_| true)
+    }
+
+    fn exit(&self, offset: usize) -> Option<(usize, bool)> {
+        self.match_parser(offset, TokenKind::Exit, |_| true)
+    }
+
+    fn activities(&self, offset: usize) -> Option<(usize, bool)> {
+        self.match_parser(offset, TokenKind::Activities, |_| true)
+    }
+
+    // `after 500ms`
+    fn after(&self, offset: usize) -> Option<(usize, Delay<'a>)> {
+        let (offset, _) = self.match_parser(offset, TokenKind::After, |_| true)?;
+        let delay = self
+            .identifier(offset)
+            .and_then(|(offset, text)| Some((offset, Delay::parse(text)?)));
+
+        if delay.is_none() {
+            self.expected(offset, TokenKind::Delay);
+        }
+        delay
+    }
+
+    fn history(&self, offset: usize) -> Option<(usize, HistoryType)> {
+        self.match_parser(offset, TokenKind::History, |token| match token.typ {
+            TokenType::History(history) => history,
+            _ => unreachable!(),
+        })
     }
 
     fn indent(&self, offset: usize) -> Option<(usize, bool)> {
-        self.match_parser(offset, |token| token.typ == TokenType::Indent, |_| true)
+        self.match_parser(offset, TokenKind::Indent, |_| true)
     }
 
     fn dedent(&self, offset: usize) -> Option<(usize, bool)> {
-        self.match_parser(
-            offset,
-            |token: &Token<'a>| token.typ == TokenType::Dedent,
-            |_| true,
-        )
+        self.match_parser(offset, TokenKind::Dedent, |_| true)
+    }
+
+    // succeeds without consuming anything if the token at offset is not on
+    // the current line
+    fn line_end(&self, offset: usize) -> Option<(usize, bool)> {
+        if self.continues_line(offset) {
+            self.expected(offset, TokenKind::EndOfLine);
+            return None;
+        }
+
+        Some((offset, true))
+    }
+
+    // `A | B -> x` is the same transition for both events. It becomes one
+    // TransitionNode per event, each pointing at its own event name.
+    fn transition(&self, offset: usize) -> Option<(usize, Vec<TransitionNode<'a>>)> {
+        let start = offset;
+        let (offset, delay) = zero_or_one(offset, |offset| self.after(offset))
+            .map(|(o, d)| (o, Some(d)))
+            .unwrap_or((offset, None));
+        let (offset, events) = match delay {
+            Some(_) => (offset, vec![]),
+            None => zero_or_one(offset, |offset| self.wildcard(offset))
+                .or_else(|| self.names(offset, TokenKind::EventSeparator))
+                .unwrap_or((offset, vec![])),
+        };
+
+        let event = events.first().map(|(event, _)| *event).unwrap_or("");
+        let (offset, transition) = self.transition_to(start, offset, event, delay)?;
+
+        if events.len() < 2 {
+            return Some((offset, vec![transition]));
+        }
+
+        let transitions = events
+            .into_iter()
+            .map(|(event, pos)| TransitionNode {
+                event,
+                pos,
+                ..transition.clone()
+            })
+            .collect();
+
+        Some((offset, transitions))
+    }
+
+    // `*`, which xstate takes for any event. Returned the way `names` returns
+    // events.
+    fn wildcard(&self, offset: usize) -> Option<(usize, Vec<(&'a str, Position)>)> {
+        let pos = self.get_token_at(offset)?.pos;
+        self.match_parser(offset, TokenKind::Wildcard, |_| vec![("*", pos.clone())])
+    }
+
+    // one or more names separated by `separator`, all on the same line. Used
+    // for the events and for the targets of a transition.
+    fn names(
+        &self,
+        offset: usize,
+        separator: TokenKind,
+    ) -> Option<(usize, Vec<(&'a str, Position)>)> {
+        let name = |offset| {
+            let (new_offset, event) = self.identifier(offset)?;
+            Some((new_offset, (event, self.get_token_at(offset)?.pos)))
+        };
+
+        let (offset, first) = name(offset)?;
+        let (offset, rest) = zero_or_more(offset, |offset| {
+            let (offset, _) = self.match_parser(offset, separator, |_| ())?;
+            if !self.continues_line(offset) {
+                self.expected(offset, TokenKind::Identifier);
+                return None;
+            }
+            name(offset)
+        })
+        .unwrap_or((offset, vec![]));
+
+        let mut names = vec![first];
+        names.extend(rest);
+        Some((offset, names))
+    }
+
+    // `done -> target` or `error -> target` inside an `invoke` block
+    fn invoke_transition(&self, offset: usize) -> Option<(usize, TransitionNode<'a>)> {
+        let (new_offset, event) = self.identifier(offset)?;
+        if event != "done" && event != "error" {
+            self.expected(offset, TokenKind::InvokeEvent);
+            return None;
+        }
+
+        self.transition_to(offset, new_offset, event, None)
     }
 
-    fn transition(&self, offset: usize) -> Option<(usize, TransitionNode<'a>)> {
+    // the part of a transition from the arrow onwards. `start` is where the
+    // transition began. A transition with an event or a delay can leave out
+    // the arrow and the target, e.g. `TICK > count`.
+    fn transition_to(
+        &self,
+        start: usize,
+        offset: usize,
+        event: &'a str,
+        delay: Option<Delay<'a>>,
+    ) -> Option<(usize, TransitionNode<'a>)> {
         let new_offset;
-        let (offset, event) =
-            zero_or_one(offset, |offset| self.identifier(offset)).unwrap_or((offset, ""));
-        let (offset, _) = self.transition_arrow(offset)?;
-        let (offset, target) = self.identifier(offset)?;
+        let can_be_targetless = !event.is_empty() || delay.is_some();
+        let (offset, internal, targets) = match self.any_transition_arrow(offset) {
+            Some((offset, internal)) => {
+                // the target has to be on the same line as the arrow. Otherwise a
+                // half typed `event ->` swallows the state on the next line.
+                if !self.continues_line(offset) {
+                    self.expected(offset, TokenKind::Identifier);
+                    return None;
+                }
+                let (offset, targets) = self.names(offset, TokenKind::Comma)?;
+                (offset, internal, targets)
+            }
+            None if can_be_targetless => (offset, None, vec![]),
+            None => return None,
+        };
 
         let condition_name;
         let action_names;
 
-        if event != "" {
-            let (offset, cn) =
-                zero_or_one(offset, |offset| self.condition(offset)).unwrap_or((offset, ""));
-            condition_name = if cn == "" { None } else { Some(cn) };
+        // delayed transitions are not transient, so the condition is optional
+        if can_be_targetless {
+            let (offset, cn) = match zero_or_one(offset, |offset| self.condition(offset)) {
+                Some((offset, cn)) => (offset, Some(cn)),
+                None => (offset, None),
+            };
+            condition_name = cn;
             // because the variables in the tuple below are in the scope of the
             // if statement, i can't just do `let (offset, action_names) = `.
             // That action_names is then only scoped inside the if condition and
@@ -380,17 +1110,120 @@ impl<'a> Parser<'a> {
 
             new_offset = offset;
         }
+        // without a target there has to be something for the transition to
+        // do. A plain `name` line is a state.
+        if targets.is_empty() && condition_name.is_none() && action_names.is_empty() {
+            return None;
+        }
+        let (new_offset, _) = self.line_end(new_offset)?;
 
         let transition_node = TransitionNode {
             event,
-            target,
+            target: targets.iter().map(|(target, _)| *target).collect(),
+            internal: internal.map(|internal| Reentry {
+                internal,
+                style: self.options.guard_style,
+            }),
             cond: condition_name,
             actions: action_names,
+            pos: self.get_token_at(start).unwrap().pos,
+            target_pos: targets.into_iter().map(|(_, pos)| pos).collect(),
+            delay,
         };
 
         Some((new_offset, transition_node))
     }
 
+    // the `-> target` of `hist H -> target`. Has to be on the same line.
+    fn history_target(&self, offset: usize) -> Option<(usize, (&'a str, Position))> {
+        let (offset, _) = self.transition_arrow(offset)?;
+        if !self.continues_line(offset) {
+            self.expected(offset, TokenKind::Identifier);
+            return None;
+        }
+        let pos = self.get_token_at(offset)?.pos;
+        let (offset, target) = self.identifier(offset)?;
+
+        Some((offset, (target, pos)))
+    }
+
+    // `entry > a > b`, `exit > c` or `activities > d`. At least one name is
+    // needed, but the tokenizer only makes these words keywords when one
+    // follows.
+    fn state_actions(&self, offset: usize) -> Option<(usize, TransitionOrState<'a>)> {
+        // rust tip: enum variants with values can be used as functions which
+        // create that variant
+        let (offset, make_line): (usize, fn(Vec<Call<'a>>) -> TransitionOrState<'a>) =
+            if let Some((offset, _)) = self.entry(offset) {
+                (offset, TransitionOrState::Entry)
+            } else if let Some((offset, _)) = self.exit(offset) {
+                (offset, TransitionOrState::Exit)
+            } else {
+                (self.activities(offset)?.0, TransitionOrState::Activities)
+            };
+        let (offset, first_action) = self.action(offset)?;
+        let (offset, mut actions) =
+            zero_or_more(offset, |offset| self.action(offset)).unwrap_or((offset, vec![]));
+        actions.insert(0, first_action);
+        let (offset, _) = self.line_end(offset)?;
+
+        Some((offset, make_line(actions)))
+    }
+
+    // `invoke src` or `invoke src as id`, followed by an optional block of
+    // `done` and `error` transitions
+    fn invoke(&self, offset: usize) -> Option<(usize, InvokeNode<'a>)> {
+        let start = offset;
+        let (offset, _) = self.match_parser(offset, TokenKind::Invoke, |_| true)?;
+        let (offset, src) = self.identifier(offset)?;
+        let (offset, id) = zero_or_one(offset, |o| {
+            let (o, _) = self.match_parser(o, TokenKind::As, |_| true)?;
+            self.identifier(o)
+        })
+        .map(|(o, id)| (o, Some(id)))
+        .unwrap_or((offset, None));
+        let (mut offset, _) = self.line_end(offset)?;
+
+        let mut on_done = vec![];
+        let mut on_error = vec![];
+
+        if let Some((new_offset, _)) = self.indent(offset) {
+            let (new_offset, transitions) = zero_or_more(new_offset, |o| {
+                self.reset_furthest_failure(o);
+
+                if let Some((no, t)) = self.invoke_transition(o) {
+                    return Some((no, Some(t)));
+                }
+
+                self.recover(o).map(|no| (no, None))
+            })
+            .unwrap_or((new_offset, vec![]));
+
+            for transition in transitions.into_iter().flatten() {
+                if transition.event == "done" {
+                    on_done.push(transition);
+                } else {
+                    on_error.push(transition);
+                }
+            }
+
+            offset = zero_or_one(new_offset, |o| self.dedent(o))
+                .map(|(o, _)| o)
+                .unwrap_or(new_offset);
+        }
+
+        Some((
+            offset,
+            InvokeNode {
+                id,
+                src,
+                on_done,
+                on_error,
+                pos: self.get_token_at(start).unwrap().pos,
+            },
+        ))
+    }
+
     // All our parsers will return an Option. If parsing was successful, return
     // Some<SomeData> else return None. We can probably write generic functions
     // which can handle these Option<T> return values. Functions like zero_or_more
@@ -398,7 +1231,11 @@ impl<'a> Parser<'a> {
     // We can use the question mark (?) operator
     // self.identifier()?;
     fn state_parser(&mut self, offset: usize) -> Option<(usize, StateNode<'a>)> {
+        let start = offset;
         let (offset, id) = self.identifier(offset)?;
+        let (offset, history) = zero_or_one(offset, |o| self.history(o))
+            .map(|(o, h)| (o, Some(h)))
+            .unwrap_or((offset, None));
         let (offset, is_parallel_state) =
             zero_or_one(offset, |offset| self.parallel_state(offset)).unwrap_or((offset, false));
 
@@ -407,10 +1244,25 @@ impl<'a> Parser<'a> {
 
         let (offset, is_initial_state) =
             zero_or_one(offset, |o| self.initial_state(o)).unwrap_or((offset, false));
+        let markers: Vec<Token<'a>> = (start + 1..offset)
+            .filter_map(|o| self.get_token_at(o))
+            .collect();
+        let (offset, history_target) = match history {
+            Some(_) => zero_or_one(offset, |o| self.history_target(o))
+                .map(|(o, t)| (o, Some(t)))
+                .unwrap_or((offset, None)),
+            None => (offset, None),
+        };
+        let (offset, _) = self.line_end(offset)?;
 
         let (mut offset, is_indent_there) =
             zero_or_one(offset, |o| self.indent(o)).unwrap_or((offset, false));
         let mut transitions: Vec<TransitionNode<'a>> = vec![];
+        let mut after: Vec<TransitionNode<'a>> = vec![];
+        let mut entry: Vec<Call<'a>> = vec![];
+        let mut exit: Vec<Call<'a>> = vec![];
+        let mut activities: Vec<Call<'a>> = vec![];
+        let mut invoke: Vec<InvokeNode<'a>> = vec![];
         let mut sub_states: Vec<(&'a str, StateNode<'a>)> = vec![];
 
         if is_indent_there {
@@ -418,38 +1270,50 @@ impl<'a> Parser<'a> {
             // StateNode. And then it became super painful to take them apart.
             let (new_offset, transitions_and_states) =
                 zero_or_more(offset, |o| -> Option<(usize, TransitionOrState)> {
+                    self.reset_furthest_failure(o);
+
                     if let Some((no, x)) = self.transition(o) {
-                        return Some((no, TransitionOrState::Transition(x)));
+                        return Some((no, TransitionOrState::Transitions(x)));
+                    }
+
+                    if let Some(x) = self.state_actions(o) {
+                        return Some(x);
+                    }
+
+                    if let Some((no, x)) = self.invoke(o) {
+                        return Some((no, TransitionOrState::Invoke(x)));
                     }
 
                     if let Some((no, x)) = self.state_parser(o) {
-                        return Some((no, TransitionOrState::State(x)));
+                        return Some((no, TransitionOrState::State(Box::new(x))));
                     }
 
-                    return None;
+                    // neither a transition nor a state. Report it and move
+                    // on to the next line.
+                    self.recover(o).map(|no| (no, TransitionOrState::Skipped))
                 })
                 .unwrap_or((offset, vec![]));
 
-            // Had to clone the list because otherwise rust complains that
-            // the values are already moved when i try to get the states
-            // in the second filter pass
-            let transitions_and_states_clone = transitions_and_states.clone();
-            transitions = transitions_and_states
-                .into_iter()
-                .filter_map(|ts| match ts {
-                    // we can convert a vector to hashmap by having the vector as a
-                    // vector of tuples of (key, val)
-                    TransitionOrState::Transition(t) => Some(t),
-                    _ => None,
-                })
-                .collect();
-            sub_states = transitions_and_states_clone
-                .into_iter()
-                .filter_map(|ts| match ts {
-                    TransitionOrState::State(t) => Some((t.id, t)),
-                    _ => None,
-                })
-                .collect();
+            // one pass which sorts every line into where it belongs. Used to
+            // be a filter pass per kind, over a clone of the list.
+            for ts in transitions_and_states {
+                match ts {
+                    TransitionOrState::Transitions(ts) => {
+                        for t in ts {
+                            match t.delay {
+                                Some(_) => after.push(t),
+                                None => transitions.push(t),
+                            }
+                        }
+                    }
+                    TransitionOrState::State(s) => sub_states.push((s.id, *s)),
+                    TransitionOrState::Entry(actions) => entry.extend(actions),
+                    TransitionOrState::Exit(actions) => exit.extend(actions),
+                    TransitionOrState::Activities(names) => activities.extend(names),
+                    TransitionOrState::Invoke(i) => invoke.push(i),
+                    TransitionOrState::Skipped => {}
+                }
+            }
 
             zero_or_more(new_offset, |o| self.dedent(o));
             offset = new_offset;
@@ -459,13 +1323,48 @@ impl<'a> Parser<'a> {
             offset = new_offset;
         }
 
+        let typ = get_state_type(
+            history.is_some(),
+            is_parallel_state,
+            is_final_state,
+            sub_states.len(),
+        );
+        let initial = get_initial_state(&typ, &sub_states);
+
+        // only states with children can be done. In an atomic state `done`
+        // is just the name of an event.
+        let on_done;
+        if typ == StateType::CompoundState || typ == StateType::ParallelState {
+            (on_done, transitions) = transitions.into_iter().partition(|t| t.event == "done");
+        } else {
+            on_done = vec![];
+        }
+
+        // the first state with a given name wins
+        let mut states = IndexMap::new();
+        let mut duplicate_states = vec![];
+        for (sub_state_id, sub_state) in sub_states {
+            if states.contains_key(sub_state_id) {
+                duplicate_states.push(sub_state);
+            } else {
+                states.insert(sub_state_id, sub_state);
+            }
+        }
+
         Some((
             offset,
             StateNode {
                 id,
-                typ: get_state_type(is_parallel_state, is_final_state, sub_states.len()),
-                initial: get_initial_state(&sub_states),
+                typ,
+                initial,
                 is_initial: is_initial_state,
+                history,
+                target: history_target.as_ref().map(|(target, _)| *target),
+                target_pos: history_target.map(|(_, pos)| pos).unwrap_or_default(),
+                entry,
+                exit,
+                activities,
+                invoke,
                 // we can convert a vector to hashmap by having the vector as a
                 // vector of tuples of (key, val)
                 // TODO: Converting transitions vector to hashmap like this merges
@@ -473,35 +1372,79 @@ impl<'a> Parser<'a> {
                 // Because all the transient transitions have the same empty string
                 // key
                 on: transitions,
-                states: sub_states.into_iter().collect(),
+                after,
+                on_done,
+                states,
+                // the identifier matched, so there is a token at start
+                pos: self.get_token_at(start).unwrap().pos,
+                duplicate_states,
+                markers,
             },
         ))
     }
 
     // Our parser returns a Result type. Which means it returns an error if the
-    // parsing fails.
-    // TODO: Define a custom error struct
-    pub fn parse(&mut self, input_str: &'a str) -> Result<StateNode<'a>, &'a str> {
-        self.tokens = tokenize(input_str)
-            .into_iter()
-            // rust tip: If you want to match partially on a enum with a value
-            // In this case i didn't care about what's inside Comment enum
-            // variant
-            .filter(|t| !matches!(t.typ, TokenType::Comment(_)))
-            .collect();
+    // parsing fails. The error is the first one found in the input. Use
+    // parse_with_recovery to get all of them.
+    #[allow(clippy::result_large_err)]
+    pub fn parse(&mut self, input_str: &'a str) -> Result<StateNode<'a>, ParseError<'a>> {
+        let ParseOutput { ast, errors, .. } = self.run(input_str, false);
 
-        if let Some((_, ast)) = self.state_parser(0) {
-            // println!("ast {:#?}", ast);
-            return Ok(ast);
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
         }
 
-        Err("MyParser: Error parsing string")
+        // parse_with_recovery always reports an error when there is no ast
+        Ok(ast.expect("no ast and no errors"))
+    }
+
+    pub fn parse_with_recovery(&mut self, input_str: &'a str) -> ParseOutput<'a> {
+        self.run(input_str, true)
+    }
+
+    fn run(&mut self, input_str: &'a str, recovering: bool) -> ParseOutput<'a> {
+        self.tokens = TokenCursor::new(Lexer::with_tab_width(input_str, self.options.tab_width));
+        self.recovering = recovering;
+        self.input_str = input_str;
+        self.end_of_input = end_position(input_str);
+        self.errors.borrow_mut().clear();
+        self.warnings.borrow_mut().clear();
+        self.reset_furthest_failure(0);
+
+        let ast = match self.state_parser(0) {
+            Some((offset, ast)) => {
+                // there can only be one root state. Anything after it would
+                // otherwise be silently dropped.
+                self.trailing_input(offset);
+                for error in validate::validate(&ast) {
+                    match error.severity {
+                        Severity::Error => self.errors.borrow_mut().push(error),
+                        Severity::Warning => self.warnings.borrow_mut().push(error),
+                    }
+                }
+                Some(ast)
+            }
+            None => {
+                // the root state itself could not be parsed
+                self.errors
+                    .borrow_mut()
+                    .push(self.error_at_furthest_failure(0));
+                None
+            }
+        };
+
+        ParseOutput {
+            ast,
+            errors: self.errors.take(),
+            warnings: self.warnings.take(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     static INPUT: &str = "abc
 % some comment
@@ -518,10 +1461,33 @@ mod tests {
     -> ast; ifyes
     -> lastState; ifno";
 
+    // positions are checked by the tokenizer tests. Comparing whole asts is
+    // easier without them.
+    fn without_positions(mut state: StateNode) -> StateNode {
+        state.pos = Position::default();
+        state.target_pos = Position::default();
+        state.markers = vec![];
+        for transition in state
+            .on
+            .iter_mut()
+            .chain(state.after.iter_mut())
+            .chain(state.on_done.iter_mut())
+        {
+            transition.pos = Position::default();
+            transition.target_pos = vec![];
+        }
+        state.states = state
+            .states
+            .into_iter()
+            .map(|(id, s)| (id, without_positions(s)))
+            .collect();
+        state
+    }
+
     #[test]
     fn test_parser() {
         let mut parser = Parser::new();
-        let ast = parser.parse(INPUT).unwrap();
+        let ast = without_positions(parser.parse(INPUT).unwrap());
 
         let expected_ast: StateNode = StateNode {
             id: "abc",
@@ -531,95 +1497,79 @@ mod tests {
             on: vec![
                 TransitionNode {
                     event: "def",
-                    target: "lmn",
+                    target: vec!["lmn"],
                     cond: None,
                     actions: vec![],
+                    ..Default::default()
                 },
                 TransitionNode {
                     event: "pasta",
-                    target: "noodles",
+                    target: vec!["noodles"],
                     cond: None,
                     actions: vec![],
+                    ..Default::default()
                 },
                 TransitionNode {
                     event: "tried",
-                    target: "that",
+                    target: vec!["that"],
                     cond: None,
-                    actions: vec!["andDoThis"],
+                    actions: vec!["andDoThis".into()],
+                    ..Default::default()
                 },
             ],
             states: vec![
-                (
-                    "lastState",
-                    StateNode {
-                        id: "lastState",
-                        typ: StateType::AtomicState,
-                        initial: None,
-                        is_initial: false,
-                        on: vec![
-                            TransitionNode {
-                                event: "",
-                                target: "ast",
-                                cond: Some("ifyes"),
-                                actions: vec![],
-                            },
-                            TransitionNode {
-                                event: "",
-                                target: "lastState",
-                                cond: Some("ifno"),
-                                actions: vec![],
-                            },
-                        ],
-                        states: HashMap::new(),
-                    },
-                ),
                 (
                     "ast",
                     StateNode {
                         id: "ast",
                         typ: StateType::ParallelState,
-                        initial: Some("nestedstate2"),
+                        initial: None,
                         is_initial: true,
                         on: vec![
                             TransitionNode {
                                 event: "opq",
-                                target: "rst",
-                                cond: Some("ifyes"),
+                                target: vec!["rst"],
+                                cond: Some("ifyes".into()),
                                 actions: vec![],
+                                ..Default::default()
                             },
                             TransitionNode {
                                 event: "uvw",
-                                target: "#abc.lastState",
+                                target: vec!["#abc.lastState"],
                                 cond: None,
                                 actions: vec![],
+                                ..Default::default()
                             },
                         ],
                         states: vec![
                             (
-                                "nestedstate2",
+                                "nestedstate1",
                                 StateNode {
-                                    id: "nestedstate2",
+                                    id: "nestedstate1",
                                     typ: StateType::AtomicState,
                                     initial: None,
-                                    is_initial: true,
+                                    is_initial: false,
                                     on: vec![],
-                                    states: HashMap::new(),
+                                    states: IndexMap::new(),
+                                    ..Default::default()
                                 },
                             ),
                             (
-                                "nestedstate1",
+                                "nestedstate2",
                                 StateNode {
-                                    id: "nestedstate1",
+                                    id: "nestedstate2",
                                     typ: StateType::AtomicState,
                                     initial: None,
-                                    is_initial: false,
+                                    is_initial: true,
                                     on: vec![],
-                                    states: HashMap::new(),
+                                    states: IndexMap::new(),
+                                    ..Default::default()
                                 },
                             ),
                         ]
                         .into_iter()
                         .collect(),
+                        ..Default::default()
                     },
                 ),
                 (
@@ -632,25 +1582,748 @@ mod tests {
                         on: vec![
                             TransitionNode {
                                 event: "",
-                                target: "ast",
-                                cond: Some("ifyes"),
+                                target: vec!["ast"],
+                                cond: Some("ifyes".into()),
                                 actions: vec![],
+                                ..Default::default()
                             },
                             TransitionNode {
                                 event: "",
-                                target: "lastState",
-                                cond: Some("ifno"),
+                                target: vec!["lastState"],
+                                cond: Some("ifno".into()),
                                 actions: vec![],
+                                ..Default::default()
                             },
                         ],
-                        states: HashMap::new(),
+                        states: IndexMap::new(),
+                        ..Default::default()
                     },
                 ),
             ]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         assert_eq!(expected_ast, ast);
     }
+
+    #[test]
+    fn test_states_keep_declaration_order() {
+        let mut parser = Parser::new();
+        let ast = parser.parse(INPUT).unwrap();
+
+        // rust tip: IndexMap equality ignores the order, like HashMap's does.
+        // So test_parser can't catch states coming out in the wrong order.
+        let ids: Vec<&str> = ast.states.keys().copied().collect();
+        assert_eq!(ids, vec!["ast", "lastState"]);
+
+        let ids: Vec<&str> = ast.states["ast"].states.keys().copied().collect();
+        assert_eq!(ids, vec!["nestedstate1", "nestedstate2"]);
+
+        let ast = parser.parse("a\n  z\n  y\n  x\n  w").unwrap();
+        let ids: Vec<&str> = ast.states.keys().copied().collect();
+        assert_eq!(ids, vec!["z", "y", "x", "w"]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut parser = Parser::new();
+        let error = parser.parse("-> abc").unwrap_err();
+
+        assert_eq!(error.message, "expected identifier, found `->`");
+        assert_eq!(error.expected, vec![TokenKind::Identifier]);
+        assert_eq!(error.token.map(|t| t.typ), Some(TokenType::TransitionArrow));
+        assert_eq!(
+            error.pos,
+            Position {
+                line_number: 0,
+                col: 0,
+                start: 0,
+                end: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_error_at_end_of_input() {
+        let mut parser = Parser::new();
+        let error = parser.parse("% only a comment").unwrap_err();
+
+        assert_eq!(error.message, "expected identifier, found end of input");
+        assert_eq!(error.token, None);
+        assert_eq!(
+            error.pos,
+            Position {
+                line_number: 0,
+                col: 16,
+                start: 16,
+                end: 16
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "abc
+  def -> ; x
+  ghi
+    jkl ->
+  -> mno
+  pqr -> stu",
+        );
+
+        let errors: Vec<(usize, usize, &str)> = output
+            .errors
+            .iter()
+            .map(|e| (e.pos.line_number, e.pos.col, &e.message[..]))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (1, 11, "expected identifier, found condition `x`"),
+                (3, 10, "expected identifier, found end of line"),
+                (4, 8, "expected condition (`;`) or `,`, found end of line"),
+            ]
+        );
+
+        let ast = without_positions(output.ast.unwrap());
+        assert_eq!(
+            ast.on,
+            vec![TransitionNode {
+                event: "pqr",
+                target: vec!["stu"],
+                cond: None,
+                actions: vec![],
+                ..Default::default()
+            }]
+        );
+        assert_eq!(ast.states.len(), 1);
+        assert!(ast.states.contains_key("ghi"));
+    }
+
+    #[test]
+    fn test_recovery_skips_nested_block() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "abc
+  def & -> x
+    ghi
+      jkl
+  mno",
+        );
+
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.errors[0].pos.line_number, 1);
+
+        let ast = output.ast.unwrap();
+        assert_eq!(ast.states.len(), 1);
+        assert!(ast.states.contains_key("mno"));
+    }
+
+    #[test]
+    fn test_trailing_input_is_an_error() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "abc
+  def
+xyz -> abc
+    ghi",
+        );
+
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(
+            output.errors[0].message,
+            "expected end of input, found identifier `xyz`"
+        );
+        assert_eq!(output.errors[0].pos.line_number, 2);
+        assert_eq!(output.errors[0].pos.col, 0);
+        assert!(output.ast.is_some());
+    }
+
+    #[test]
+    fn test_dedent_to_unknown_level() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "abc
+  def
+      ghi
+    jkl
+  mno",
+        );
+
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(
+            output.errors[0].message,
+            "dedent does not match any outer indentation level"
+        );
+        assert_eq!(output.errors[0].pos.line_number, 3);
+        assert_eq!(output.errors[0].pos.col, 0);
+        assert_eq!(output.errors[0].pos.end - output.errors[0].pos.start, 4);
+
+        let ast = output.ast.unwrap();
+        assert!(ast.states.contains_key("def"));
+        assert!(ast.states.contains_key("mno"));
+    }
+
+    #[test]
+    fn test_tab_indentation() {
+        let input = "abc\n\tdef\n\t\tghi\n\tjkl\n        mno";
+
+        // a tab is 4 columns by default, so `mno` is nested under `jkl`
+        let mut parser = Parser::new();
+        let ast = parser.parse(input).unwrap();
+        assert_eq!(ast.states.len(), 2);
+        assert_eq!(ast.states["def"].states.len(), 1);
+        assert_eq!(ast.states["jkl"].states.len(), 1);
+
+        // with a tab width of 8 it's a sibling of `jkl`
+        let mut parser = Parser::with_options(ParserOptions {
+            tab_width: 8,
+            ..ParserOptions::default()
+        });
+        let ast = parser.parse(input).unwrap();
+        assert_eq!(ast.states.len(), 3);
+    }
+
+    #[test]
+    fn test_trailing_input_is_a_warning_when_lenient() {
+        let mut parser = Parser::with_options(ParserOptions {
+            lenient: true,
+            ..ParserOptions::default()
+        });
+        let output = parser.parse_with_recovery("abc\n  def\nxyz\nuvw ?");
+
+        assert_eq!(output.errors, vec![]);
+        assert_eq!(output.warnings.len(), 2);
+        assert!(output
+            .warnings
+            .iter()
+            .all(|w| w.severity == Severity::Warning));
+        assert!(parser.parse("abc\n  def\nxyz").is_ok());
+    }
+
+    #[test]
+    fn test_lex_error_is_reported() {
+        let mut parser = Parser::new();
+        let error = parser.parse("abc\n  def -> ghi;").unwrap_err();
+
+        assert_eq!(error.message, "expected a condition name after `;`");
+        assert_eq!(error.pos.line_number, 1);
+        assert_eq!(error.pos.col, 12);
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics(input in "(\\PC|\\s)*") {
+            Parser::new().parse_with_recovery(&input);
+        }
+    }
+
+    #[test]
+    fn test_parse_stops_tokenizing_at_first_error() {
+        let mut input = String::from("abc\n  def -> ;\n");
+        for i in 0..1000 {
+            input.push_str(&format!("  state{} -> abc\n", i));
+        }
+
+        let mut parser = Parser::new();
+        let error = parser.parse(&input).unwrap_err();
+        assert_eq!(error.pos.line_number, 1);
+        assert!(parser.tokens.tokens_read() < 10);
+
+        // with recovery, the whole input gets tokenized
+        let output = parser.parse_with_recovery(&input);
+        assert_eq!(output.errors.len(), 1);
+        assert!(parser.tokens.tokens_read() > 3000);
+    }
+
+    #[test]
+    fn test_entry_and_exit_actions() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "abc
+  entry > connect
+  def
+    entry > startTimer > log
+    exit > stopTimer
+    entry > highlight
+    TICK -> def > count
+  exit > disconnect",
+            )
+            .unwrap();
+
+        assert_eq!(ast.entry, vec!["connect"]);
+        assert_eq!(ast.exit, vec!["disconnect"]);
+
+        let def = &ast.states["def"];
+        assert_eq!(def.entry, vec!["startTimer", "log", "highlight"]);
+        assert_eq!(def.exit, vec!["stopTimer"]);
+        assert_eq!(def.on.len(), 1);
+
+        // states can still be called `entry` and `exit`
+        let ast = parser
+            .parse("abc\n  GO -> entry > log\n  entry\n    STOP -> exit > log\n  exit")
+            .unwrap();
+        assert_eq!(ast.on[0].target, vec!["entry"]);
+        assert_eq!(ast.on[0].actions, vec!["log"]);
+        assert_eq!(ast.states["entry"].on[0].target, vec!["exit"]);
+        assert_eq!(ast.states["entry"].on[0].actions, vec!["log"]);
+
+        let error = parser.parse("abc\n  entry > a -> b").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected action (`>`) or end of line, found `->`"
+        );
+    }
+
+    #[test]
+    fn test_activities() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "alarm
+  ringing
+    activities > beeping > flashing
+    entry > log
+    activities > vibrating
+    SNOOZE -> snoozed
+  snoozed
+    activities -> ringing",
+            )
+            .unwrap();
+
+        assert_eq!(
+            ast.states["ringing"].activities,
+            vec!["beeping", "flashing", "vibrating"]
+        );
+        assert_eq!(ast.states["ringing"].entry, vec!["log"]);
+        // not followed by `>`, so it's an event
+        assert_eq!(ast.states["snoozed"].activities, Vec::<&str>::new());
+        assert_eq!(ast.states["snoozed"].on[0].event, "activities");
+
+        // or a target with actions
+        let ast = parser
+            .parse("alarm\n  RING -> activities > log\n  activities")
+            .unwrap();
+        assert_eq!(ast.on[0].target, vec!["activities"]);
+        assert_eq!(ast.on[0].actions, vec!["log"]);
+    }
+
+    #[test]
+    fn test_history_states() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "player
+  hist H* -> playing
+  playing
+    OPEN_MENU -> menu
+  paused H
+  menu
+    CLOSE -> hist",
+        );
+
+        assert_eq!(output.errors, vec![]);
+        assert_eq!(output.warnings, vec![]);
+        let ast = output.ast.unwrap();
+        // history states are skipped when picking the initial state
+        assert_eq!(ast.initial, Some("playing"));
+
+        let hist = &ast.states["hist"];
+        assert_eq!(hist.typ, StateType::History);
+        assert_eq!(hist.history, Some(HistoryType::Deep));
+        assert_eq!(hist.target, Some("playing"));
+        assert_eq!(hist.target_pos.col, 13);
+
+        let paused = &ast.states["paused"];
+        assert_eq!(paused.history, Some(HistoryType::Shallow));
+        assert_eq!(paused.target, None);
+
+        let error = parser.parse("abc\n  hist H ->\n  def").unwrap_err();
+        assert_eq!(error.message, "expected identifier, found end of line");
+    }
+
+    #[test]
+    fn test_after_transitions() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "light
+  green
+    after 500ms -> yellow
+    after 2s -> red; isBroken > report
+    after 2 -> red
+    after LONG_DELAY -> red
+    after 1.5m -> red
+    TIMER -> yellow
+  yellow
+  red",
+            )
+            .unwrap();
+
+        let green = &ast.states["green"];
+        let delays: Vec<_> = green
+            .after
+            .iter()
+            .map(|t| t.delay.clone().unwrap())
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Delay::Millis(500),
+                Delay::Millis(2000),
+                Delay::Millis(2),
+                Delay::Named("LONG_DELAY"),
+                Delay::Millis(90_000),
+            ]
+        );
+        assert_eq!(green.on.len(), 1);
+
+        assert_eq!(
+            serde_json::to_value(green).unwrap()["after"],
+            serde_json::json!({
+                "500": [{ "target": "yellow" }],
+                "2000": [{ "target": "red", "cond": "isBroken", "actions": ["report"] }],
+                "2": [{ "target": "red" }],
+                "LONG_DELAY": [{ "target": "red" }],
+                "90000": [{ "target": "red" }],
+            })
+        );
+
+        let error = parser.parse("abc\n  after 5h -> abc").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected delay (like `500ms`, `2s`, `1m` or a name), found identifier `5h`"
+        );
+        let error = parser.parse("abc\n  after .5s -> abc").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected delay (like `500ms`, `2s`, `1m` or a name), found identifier `.5s`"
+        );
+        assert!(parser.parse("abc\n  after #foo -> abc").is_err());
+        let error = parser.parse("abc\n  after 0.0001ms -> abc").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected delay (like `500ms`, `2s`, `1m` or a name), found identifier `0.0001ms`"
+        );
+        assert!(parser
+            .parse("abc\n  after 99999999999999999999999m -> abc")
+            .is_err());
+    }
+
+    #[test]
+    fn test_invoke() {
+        let mut parser = Parser::new();
+        let output = parser.parse_with_recovery(
+            "user
+  loading
+    invoke fetchUser as userLoader
+      done -> loaded > saveUser
+      error -> failed; isFatal
+      error -> loading
+    invoke logVisit
+    CANCEL -> failed
+  loaded
+  failed",
+        );
+
+        assert_eq!(output.errors, vec![]);
+        assert_eq!(output.warnings, vec![]);
+        let ast = output.ast.unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast.states["loading"]).unwrap()["invoke"],
+            serde_json::json!([
+                {
+                    "id": "userLoader",
+                    "src": "fetchUser",
+                    "onDone": [{ "target": "loaded", "actions": ["saveUser"] }],
+                    "onError": [
+                        { "target": "failed", "cond": "isFatal" },
+                        { "target": "loading" },
+                    ],
+                },
+                { "src": "logVisit" },
+            ])
+        );
+        assert_eq!(ast.states["loading"].on.len(), 1);
+
+        let error = parser
+            .parse("abc\n  invoke fetch\n    finished -> abc")
+            .unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected `done` or `error`, found identifier `finished`"
+        );
+    }
+
+    #[test]
+    fn test_on_done() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "checkout
+  payment
+    card
+      PAY -> paid
+    paid$
+    done -> shipping > sendReceipt
+  shipping
+    done -> payment",
+            )
+            .unwrap();
+
+        let payment = &ast.states["payment"];
+        assert_eq!(payment.on, vec![]);
+        assert_eq!(
+            serde_json::to_value(payment).unwrap()["onDone"],
+            serde_json::json!([{ "target": "shipping", "actions": ["sendReceipt"] }])
+        );
+
+        // `shipping` has no children, so `done` is an ordinary event
+        let shipping = &ast.states["shipping"];
+        assert_eq!(shipping.on_done, vec![]);
+        assert_eq!(shipping.on[0].event, "done");
+    }
+
+    #[test]
+    fn test_guard_expressions() {
+        let input = "door
+  closed
+    OPEN -> opened; isValid && !(isLocked || isBusy) > log
+    -> opened; !isLocked
+  opened";
+
+        let ast = Parser::new().parse(input).unwrap();
+        let closed = &ast.states["closed"];
+        assert_eq!(
+            closed.on[0].cond.as_ref().unwrap().guard,
+            Guard::And(vec![
+                Guard::Named("isValid".into()),
+                Guard::Not(Box::new(Guard::Or(vec![
+                    Guard::Named("isLocked".into()),
+                    Guard::Named("isBusy".into())
+                ]))),
+            ])
+        );
+        assert_eq!(
+            serde_json::to_value(closed).unwrap()["on"],
+            serde_json::json!([
+                {
+                    "event": "OPEN",
+                    "target": "opened",
+                    "cond": "isValid && !(isLocked || isBusy)",
+                    "actions": ["log"],
+                },
+                { "event": "", "target": "opened", "cond": "!isLocked" },
+            ])
+        );
+
+        let ast = Parser::with_options(ParserOptions {
+            guard_style: GuardStyle::Objects,
+            ..ParserOptions::default()
+        })
+        .parse(input)
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast.states["closed"]).unwrap()["on"][1],
+            serde_json::json!({
+                "event": "",
+                "target": "opened",
+                "guard": { "type": "xstate.not", "guards": ["isLocked"] },
+            })
+        );
+
+        let mut parser = Parser::new();
+        let error = parser.parse("abc\n  GO -> abc; (a || b").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected `&&` or `||` or `)`, found end of input"
+        );
+        let error = parser.parse("abc\n  GO -> abc; a && > log").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected condition (`;`), found action `log`"
+        );
+    }
+
+    #[test]
+    fn test_event_alternation() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "search
+  focused
+    BLUR | ESCAPE | CLICK_OUTSIDE -> idle; isEmpty > clear
+  idle",
+            )
+            .unwrap();
+
+        let on = &ast.states["focused"].on;
+        let events: Vec<(&str, usize, usize)> = on
+            .iter()
+            .map(|t| (t.event, t.pos.col, t.pos.end - t.pos.start))
+            .collect();
+        assert_eq!(
+            events,
+            vec![("BLUR", 4, 4), ("ESCAPE", 11, 6), ("CLICK_OUTSIDE", 20, 13)]
+        );
+        assert!(on
+            .iter()
+            .all(|t| t.target == vec!["idle"] && t.actions == vec!["clear"] && t.cond.is_some()));
+
+        let error = parser.parse("abc\n  A | -> abc").unwrap_err();
+        assert_eq!(error.message, "expected identifier, found `->`");
+    }
+
+    #[test]
+    fn test_multiple_targets() {
+        let ast = Parser::new()
+            .parse(
+                "m
+  idle
+    GO -> #a, #b > log
+  active&
+    a
+    b",
+            )
+            .unwrap();
+
+        let go = &ast.states["idle"].on[0];
+        assert_eq!(go.target, vec!["#a", "#b"]);
+        let cols: Vec<usize> = go.target_pos.iter().map(|p| p.col).collect();
+        assert_eq!(cols, vec![10, 14]);
+        assert_eq!(
+            serde_json::to_value(go).unwrap(),
+            serde_json::json!({ "event": "GO", "target": ["#a", "#b"], "actions": ["log"] })
+        );
+    }
+
+    #[test]
+    fn test_targetless_and_internal_transitions() {
+        let ast = Parser::new()
+            .parse(
+                "counter
+  active
+    TICK > increment
+    RESET; canReset > reset
+    EDIT ~> .editing
+    RESTART => active
+    after 1s > tick
+    editing",
+            )
+            .unwrap();
+
+        let active = serde_json::to_value(&ast.states["active"]).unwrap();
+        assert_eq!(
+            active["on"],
+            serde_json::json!([
+                { "event": "TICK", "actions": ["increment"] },
+                { "event": "RESET", "cond": "canReset", "actions": ["reset"] },
+                { "event": "EDIT", "target": ".editing", "internal": true },
+                { "event": "RESTART", "target": "active", "internal": false },
+            ])
+        );
+        assert_eq!(
+            active["after"],
+            serde_json::json!({ "1000": [{ "actions": ["tick"] }] })
+        );
+        assert_eq!(ast.states["active"].states.len(), 1);
+
+        // v5 calls it `reenter`, the other way around
+        let ast = Parser::with_options(ParserOptions {
+            guard_style: GuardStyle::Objects,
+            ..ParserOptions::default()
+        })
+        .parse("counter\n  EDIT ~> .editing\n  RESTART => counter\n  editing")
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast).unwrap()["on"],
+            serde_json::json!([
+                { "event": "EDIT", "target": ".editing", "reenter": false },
+                { "event": "RESTART", "target": "counter", "reenter": true },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_wildcard_event() {
+        let ast = Parser::new()
+            .parse(
+                "fetch*
+  loading
+    DONE -> idle
+    * -> failed > logUnexpected
+  idle
+  failed",
+            )
+            .unwrap();
+
+        assert!(ast.is_initial);
+        assert_eq!(
+            serde_json::to_value(&ast.states["loading"]).unwrap()["on"][1],
+            serde_json::json!({ "event": "*", "target": "failed", "actions": ["logUnexpected"] })
+        );
+    }
+
+    #[test]
+    fn test_action_and_guard_arguments() {
+        let mut parser = Parser::new();
+        let ast = parser
+            .parse(
+                "counter
+  active
+    entry > log(\"entered\")
+    INC > assign(count, 0) > notify()
+    GO -> active; hasRole(\"admin\", 2, true) && isReady",
+            )
+            .unwrap();
+
+        let active = &ast.states["active"];
+        assert_eq!(
+            active.entry,
+            vec![Call {
+                name: "log",
+                params: vec![Literal::String("entered")],
+            }]
+        );
+
+        let json = serde_json::to_value(active).unwrap();
+        assert_eq!(
+            json["on"][0]["actions"],
+            serde_json::json!([{ "type": "assign", "params": ["count", 0] }, "notify"])
+        );
+        assert_eq!(
+            json["on"][1]["cond"],
+            serde_json::json!("hasRole(\"admin\", 2, true) && isReady")
+        );
+
+        let ast = Parser::with_options(ParserOptions {
+            guard_style: GuardStyle::Objects,
+            ..ParserOptions::default()
+        })
+        .parse("abc\n  GO -> abc; hasRole(\"admin\") && isReady")
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast.on[0]).unwrap()["guard"],
+            serde_json::json!({
+                "type": "xstate.and",
+                "guards": [{ "type": "hasRole", "params": ["admin"] }, "isReady"],
+            })
+        );
+
+        // a single guard keeps its arguments in the combined style too
+        let ast = parser
+            .parse("abc\n  GO -> abc; hasRole(\"admin\")")
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast.on[0]).unwrap()["cond"],
+            serde_json::json!({ "type": "hasRole", "params": ["admin"] })
+        );
+
+        let error = parser.parse("abc\n  GO > log(\"a\" 1)").unwrap_err();
+        assert_eq!(error.message, "expected `,` or `)`, found identifier `1`");
+    }
 }
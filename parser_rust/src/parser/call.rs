@@ -0,0 +1,203 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+// Number.MAX_SAFE_INTEGER in js, 2^53 - 1
+pub const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+// A value passed to an action or a condition, e.g. the `"admin"` in
+// `; hasRole("admin")`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Literal<'a> {
+    String(&'a str),
+    // kept as it was written, so `1.50` stays `1.50` when printed back.
+    // rust tip: f64 is not Eq, and every node in the ast derives Eq.
+    Number(&'a str),
+    Bool(bool),
+    // a bare name, e.g. the `count` in `assign(count, 0)`. Serialized as a
+    // string.
+    Name(&'a str),
+}
+
+// An action or a condition by name, with the arguments it was given, e.g.
+// `log("entered")`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Call<'a> {
+    pub name: &'a str,
+    pub params: Vec<Literal<'a>>,
+}
+
+impl<'a> Literal<'a> {
+    // an identifier token in an argument list. `5h` is not a number, so it's
+    // a name. Neither are `inf`, `NaN` or `1e400`, which rust would parse but
+    // json can't hold.
+    pub fn from_identifier(text: &'a str) -> Self {
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let is_number = digits.starts_with(|c: char| c.is_ascii_digit())
+            && matches!(text.parse::<f64>(), Ok(number) if number.is_finite());
+
+        match text {
+            "true" => Literal::Bool(true),
+            "false" => Literal::Bool(false),
+            _ if is_number => Literal::Number(text),
+            _ => Literal::Name(text),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Literal<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::String(text) => write!(f, "\"{}\"", text),
+            Literal::Number(text) | Literal::Name(text) => f.write_str(text),
+            Literal::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// prints the call back the way it was written. Used for the name of a
+// combined guard.
+impl<'a> fmt::Display for Call<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)?;
+        if self.params.is_empty() {
+            return Ok(());
+        }
+
+        f.write_str("(")?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl<'a> Serialize for Literal<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Literal::String(text) | Literal::Name(text) => serializer.serialize_str(text),
+            Literal::Bool(value) => serializer.serialize_bool(*value),
+            // integers stay integers in the json, as long as js can hold them
+            // exactly. Bigger ones go over as floats, like JSON.parse would
+            // read them.
+            Literal::Number(text) => match text.parse::<i64>() {
+                Ok(number) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&number) => {
+                    serializer.serialize_i64(number)
+                }
+                _ => serializer.serialize_f64(text.parse().unwrap()),
+            },
+        }
+    }
+}
+
+// Just the name when there are no arguments, like before arguments existed.
+// Otherwise the object form xstate takes, e.g.
+// { type: "log", params: ["entered"] }
+impl<'a> Serialize for Call<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.params.is_empty() {
+            return serializer.serialize_str(self.name);
+        }
+
+        let mut object = serializer.serialize_struct("Call", 2)?;
+        object.serialize_field("type", self.name)?;
+        object.serialize_field("params", &self.params)?;
+        object.end()
+    }
+}
+
+impl<'a> From<&'a str> for Call<'a> {
+    fn from(name: &'a str) -> Self {
+        Call {
+            name,
+            params: vec![],
+        }
+    }
+}
+
+// rust tip: this lets a Vec<Call> be compared with a vec of names, which keeps
+// the tests short
+impl<'a, 'b> PartialEq<&'b str> for Call<'a> {
+    fn eq(&self, name: &&'b str) -> bool {
+        self.params.is_empty() && self.name == *name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_finite_numbers_are_numbers() {
+        let literals: Vec<Literal> = ["0", "-1.5", "2e3", "5h", "inf", "NaN", "-infinity", "1e400"]
+            .iter()
+            .map(|text| Literal::from_identifier(text))
+            .collect();
+
+        assert_eq!(
+            literals,
+            vec![
+                Literal::Number("0"),
+                Literal::Number("-1.5"),
+                Literal::Number("2e3"),
+                Literal::Name("5h"),
+                Literal::Name("inf"),
+                Literal::Name("NaN"),
+                Literal::Name("-infinity"),
+                Literal::Name("1e400"),
+            ]
+        );
+    }
+
+    #[test]
+    fn serializes_as_a_name_or_an_object() {
+        let call = Call {
+            name: "assign",
+            params: vec![
+                Literal::Name("count"),
+                Literal::Number("0"),
+                Literal::Number("-1.5"),
+                Literal::String("a b"),
+                Literal::Bool(true),
+            ],
+        };
+
+        assert_eq!(call.to_string(), "assign(count, 0, -1.5, \"a b\", true)");
+        assert_eq!(
+            serde_json::to_value(&call).unwrap(),
+            serde_json::json!({ "type": "assign", "params": ["count", 0, -1.5, "a b", true] })
+        );
+        assert_eq!(
+            serde_json::to_value(Call::from("log")).unwrap(),
+            serde_json::json!("log")
+        );
+    }
+
+    #[test]
+    fn integers_js_cannot_hold_are_floats() {
+        let params = vec![
+            Literal::Number("9007199254740991"),
+            Literal::Number("-9007199254740991"),
+            Literal::Number("9007199254740993"),
+            Literal::Number("99999999999999999999"),
+        ];
+
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            serde_json::json!([
+                9007199254740991i64,
+                -9007199254740991i64,
+                9007199254740992.0,
+                1e20
+            ])
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use super::tokenizer::{Lexer, Token, TokenType};
+use std::cell::RefCell;
+
+// The parser backtracks a lot, so it can't simply consume the lexer as an
+// iterator (see rubber-duck.md items 7 and 8). The cursor sits between the
+// two. It pulls tokens out of the lexer only when the parser asks for a token
+// it has not seen yet, and it keeps every token it has pulled.
+//
+// Offsets are the checkpoints. A parser remembers the offset it started at
+// and, if it fails, its caller carries on from that offset. Rewinding is free
+// because the tokens before the furthest offset are still in the buffer.
+pub struct TokenCursor<'a> {
+    lexer: RefCell<Lexer<'a>>,
+    tokens: RefCell<Vec<Token<'a>>>,
+}
+
+impl<'a> TokenCursor<'a> {
+    pub fn new(lexer: Lexer<'a>) -> TokenCursor<'a> {
+        TokenCursor {
+            lexer: RefCell::new(lexer),
+            tokens: RefCell::new(vec![]),
+        }
+    }
+
+    // Comments are dropped here. The parser never wants them.
+    pub fn get(&self, offset: usize) -> Option<Token<'a>> {
+        let mut tokens = self.tokens.borrow_mut();
+
+        while tokens.len() <= offset {
+            let token = self
+                .lexer
+                .borrow_mut()
+                // rust tip: If you want to match partially on a enum with a
+                // value. In this case i didn't care about what's inside
+                // Comment enum variant
+                .find(|t| !matches!(t.typ, TokenType::Comment(_)))?;
+            tokens.push(token);
+        }
+
+        Some(tokens[offset].clone())
+    }
+
+    // how many tokens have been pulled out of the lexer so far
+    #[cfg(test)]
+    pub fn tokens_read(&self) -> usize {
+        self.tokens.borrow().len()
+    }
+}
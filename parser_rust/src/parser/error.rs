@@ -0,0 +1,103 @@
+use super::tokenizer::{Position, Token, TokenKind, TokenType};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// The error returned from Parser::parse. It serializes across the wasm
+// boundary so that the editor can show the message and point at the exact
+// place in the sketch which could not be parsed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct ParseError<'a> {
+    pub message: String,
+    pub severity: Severity,
+    pub pos: Position,
+    // the token at which parsing failed. None if we ran out of tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Token<'a>>,
+    // all the kinds of tokens which would have been accepted at `pos`
+    pub expected: Vec<TokenKind>,
+    // other places in the sketch the error is about, e.g. the first
+    // declaration of a state which is declared twice
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<Position>,
+}
+
+impl<'a> ParseError<'a> {
+    pub fn unexpected_token(token: Token<'a>, expected: Vec<TokenKind>) -> Self {
+        let message = match token.typ {
+            // the tokenizer already knows what went wrong
+            TokenType::Error(error) => error.to_string(),
+            _ => message(&expected, &token.typ.to_string()),
+        };
+
+        ParseError {
+            message,
+            severity: Severity::Error,
+            pos: token.pos.clone(),
+            token: Some(token),
+            expected,
+            related: vec![],
+        }
+    }
+
+    // `what` describes where the input ended, e.g. "end of line" or
+    // "end of input"
+    pub fn unexpected_end(pos: Position, what: &str, expected: Vec<TokenKind>) -> Self {
+        ParseError {
+            message: message(&expected, what),
+            severity: Severity::Error,
+            pos,
+            token: None,
+            expected,
+            related: vec![],
+        }
+    }
+
+    // for mistakes found after parsing, when the sketch is syntactically
+    // fine but does not make sense as a statechart
+    pub fn invalid(message: String, pos: Position, related: Vec<Position>) -> Self {
+        ParseError {
+            message,
+            severity: Severity::Error,
+            pos,
+            token: None,
+            expected: vec![],
+            related,
+        }
+    }
+
+    pub fn into_warning(self) -> Self {
+        ParseError {
+            severity: Severity::Warning,
+            ..self
+        }
+    }
+}
+
+fn message(expected: &[TokenKind], found: &str) -> String {
+    if expected.is_empty() {
+        return format!("unexpected {}", found);
+    }
+
+    let expected_str: Vec<String> = expected.iter().map(|k| k.to_string()).collect();
+    format!("expected {}, found {}", expected_str.join(" or "), found)
+}
+
+impl<'a> fmt::Display for ParseError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message,
+            self.pos.line_number + 1,
+            self.pos.col + 1
+        )
+    }
+}
+
+impl<'a> std::error::Error for ParseError<'a> {}
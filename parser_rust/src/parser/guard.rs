@@ -0,0 +1,191 @@
+use super::call::Call;
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+use std::fmt;
+
+// The condition of a transition, e.g. `; isValid && !(isLocked || isBusy)`.
+// `!` binds tighter than `&&`, which binds tighter than `||`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Guard<'a> {
+    Named(Call<'a>),
+    Not(Box<Guard<'a>>),
+    And(Vec<Guard<'a>>),
+    Or(Vec<Guard<'a>>),
+}
+
+// How a guard shows up in the json. content.js picks it by name, e.g.
+// `{ guardStyle: "objects" }`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardStyle {
+    // xstate v4. The guard goes under `cond`, and an expression is one named
+    // guard whose name is the whole expression, e.g. "isValid && !isLocked".
+    // The machine options have to implement it.
+    #[default]
+    Combined,
+    // xstate v5. The guard goes under `guard`, and an expression is nested
+    // objects with the type names of v5's and(), or() and not() guards, e.g.
+    // { type: "xstate.and", guards: ["isValid", { type: "xstate.not", ... }] }.
+    // Only the single names have to be implemented. Transitions written with
+    // `~>` or `=>` get v5's `reenter` instead of `internal`.
+    Objects,
+}
+
+// A guard along with how it should be serialized. Parser::with_options picks
+// the style for all the guards in a sketch.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Condition<'a> {
+    pub guard: Guard<'a>,
+    pub style: GuardStyle,
+}
+
+impl<'a> Guard<'a> {
+    // how tightly the guard holds together when printed. Used to decide
+    // where parentheses are needed.
+    fn precedence(&self) -> u8 {
+        match self {
+            Guard::Or(_) => 0,
+            Guard::And(_) => 1,
+            Guard::Not(_) | Guard::Named(_) => 2,
+        }
+    }
+
+    fn fmt_operand(&self, operand: &Guard, f: &mut fmt::Formatter) -> fmt::Result {
+        if operand.precedence() < self.precedence() {
+            write!(f, "({})", operand)
+        } else {
+            write!(f, "{}", operand)
+        }
+    }
+}
+
+// prints the guard back with as few parentheses as possible. This is the name
+// of the combined guard.
+impl<'a> fmt::Display for Guard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (operands, operator) = match self {
+            Guard::Named(call) => return write!(f, "{}", call),
+            Guard::Not(guard) => {
+                f.write_str("!")?;
+                return self.fmt_operand(guard, f);
+            }
+            Guard::And(guards) => (guards, " && "),
+            Guard::Or(guards) => (guards, " || "),
+        };
+
+        for (i, operand) in operands.iter().enumerate() {
+            if i > 0 {
+                f.write_str(operator)?;
+            }
+            self.fmt_operand(operand, f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// the Objects style. A name is serialized as a plain string, like any named
+// guard in xstate, or as { type, params } when it has arguments.
+impl<'a> Serialize for Guard<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (typ, guards) = match self {
+            Guard::Named(call) => return call.serialize(serializer),
+            Guard::Not(guard) => ("xstate.not", std::slice::from_ref(guard.as_ref())),
+            Guard::And(guards) => ("xstate.and", &guards[..]),
+            Guard::Or(guards) => ("xstate.or", &guards[..]),
+        };
+
+        let mut object = serializer.serialize_struct("Guard", 2)?;
+        object.serialize_field("type", typ)?;
+        object.serialize_field("guards", guards)?;
+        object.end()
+    }
+}
+
+// The key depends on the style, so a condition serializes as a map with a
+// single entry. Transitions flatten it into themselves, e.g.
+// { target: "a", cond: "isValid" } or { target: "a", guard: "isValid" }.
+impl<'a> Serialize for Condition<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match (self.style, &self.guard) {
+            // a single name keeps its arguments
+            (GuardStyle::Combined, Guard::Named(call)) => map.serialize_entry("cond", call)?,
+            (GuardStyle::Combined, guard) => map.serialize_entry("cond", &guard.to_string())?,
+            (GuardStyle::Objects, guard) => map.serialize_entry("guard", guard)?,
+        }
+        map.end()
+    }
+}
+
+// rust tip: implementing From gives us `into()` for free. Handy in tests,
+// where most conditions are a single name.
+impl<'a> From<&'a str> for Condition<'a> {
+    fn from(name: &'a str) -> Self {
+        Condition {
+            guard: Guard::Named(name.into()),
+            style: GuardStyle::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Guard<'_> {
+        Guard::Named(name.into())
+    }
+
+    fn not(guard: Guard) -> Guard {
+        Guard::Not(Box::new(guard))
+    }
+
+    #[test]
+    fn prints_with_the_parentheses_it_needs() {
+        let guard = Guard::And(vec![
+            named("isValid"),
+            not(Guard::Or(vec![named("isLocked"), named("isBusy")])),
+            Guard::Or(vec![named("a"), Guard::And(vec![named("b"), named("c")])]),
+        ]);
+
+        assert_eq!(
+            guard.to_string(),
+            "isValid && !(isLocked || isBusy) && (a || b && c)"
+        );
+        assert_eq!(not(not(named("a"))).to_string(), "!!a");
+    }
+
+    #[test]
+    fn serializes_in_both_styles() {
+        let guard = Guard::And(vec![named("isValid"), not(named("isLocked"))]);
+
+        let combined = Condition {
+            guard: guard.clone(),
+            style: GuardStyle::Combined,
+        };
+        assert_eq!(
+            serde_json::to_value(&combined).unwrap(),
+            serde_json::json!({ "cond": "isValid && !isLocked" })
+        );
+
+        let objects = Condition {
+            guard,
+            style: GuardStyle::Objects,
+        };
+        assert_eq!(
+            serde_json::to_value(&objects).unwrap(),
+            serde_json::json!({
+                "guard": {
+                    "type": "xstate.and",
+                    "guards": ["isValid", { "type": "xstate.not", "guards": ["isLocked"] }],
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use super::StateNode;
+
+// Why a target could not be resolved. `segment` is the index of the part of
+// the target (split at dots) which did not match, and `candidates` are the
+// names which would have been accepted there.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Unresolved<'a> {
+    pub segment: usize,
+    pub candidates: Vec<&'a str>,
+}
+
+// Resolves a transition target the way xstate does and returns the absolute
+// path of the target state, starting with the root state's id.
+// - `#id.child` starts at the state with that id
+// - `.child` starts at the source state itself
+// - `sibling.child` starts at the source state's parent. The root state has
+//   no parent, so for it this starts at the root state itself.
+// `source` is the absolute path of the state the transition belongs to.
+pub fn resolve_target<'a>(
+    root: &StateNode<'a>,
+    source: &[&'a str],
+    target: &str,
+) -> Result<Vec<&'a str>, Unresolved<'a>> {
+    let segments: Vec<&str> = target.split('.').collect();
+
+    let (mut path, rest) = if let Some(id) = segments[0].strip_prefix('#') {
+        match find_by_id(root, id, &mut vec![]) {
+            Some(path) => (path, &segments[1..]),
+            None => {
+                let mut candidates = vec![];
+                all_ids(root, &mut candidates);
+                return Err(Unresolved {
+                    segment: 0,
+                    candidates,
+                });
+            }
+        }
+    } else if segments[0].is_empty() {
+        (source.to_vec(), &segments[1..])
+    } else if source.len() == 1 {
+        (source.to_vec(), &segments[..])
+    } else {
+        (source[..source.len() - 1].to_vec(), &segments[..])
+    };
+
+    // only a source path which isn't in the ast gets us here. There is
+    // nothing sensible to suggest then.
+    let mut state = match state_at(root, &path) {
+        Some(state) => state,
+        None => {
+            return Err(Unresolved {
+                segment: 0,
+                candidates: vec![],
+            })
+        }
+    };
+    let first_segment = segments.len() - rest.len();
+
+    for (i, name) in rest.iter().enumerate() {
+        match state.states.get_key_value(*name) {
+            Some((id, child)) => {
+                path.push(*id);
+                state = child;
+            }
+            None => {
+                return Err(Unresolved {
+                    segment: first_segment + i,
+                    candidates: state.states.keys().copied().collect(),
+                })
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+// None when `path` doesn't lead to a state, e.g. a path through a duplicate
+// state, which is not in `states`
+pub fn state_at<'b, 'a>(root: &'b StateNode<'a>, path: &[&str]) -> Option<&'b StateNode<'a>> {
+    path.get(1..)?
+        .iter()
+        .try_fold(root, |state, name| state.states.get(*name))
+}
+
+// every state gets its name as id in the generated config, so `#id` can
+// point at any state. The first one in the sketch wins.
+fn find_by_id<'a>(
+    state: &StateNode<'a>,
+    id: &str,
+    path: &mut Vec<&'a str>,
+) -> Option<Vec<&'a str>> {
+    path.push(state.id);
+
+    if state.id == id {
+        return Some(path.clone());
+    }
+
+    for child in state.states.values() {
+        if let Some(found) = find_by_id(child, id, path) {
+            return Some(found);
+        }
+    }
+
+    path.pop();
+    None
+}
+
+fn all_ids<'a>(state: &StateNode<'a>, ids: &mut Vec<&'a str>) {
+    ids.push(state.id);
+    for child in state.states.values() {
+        all_ids(child, ids);
+    }
+}
+
+// The candidates closest to `name`, best first. Anything which needs more
+// edits than about a third of its length is probably not a typo.
+pub fn suggestions<'a>(name: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut close: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|c| (edit_distance(name, c), *c))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+    // rust tip: sort_by_key is stable, so candidates which are equally close
+    // stay in the order they appear in the sketch
+    close.sort_by_key(|(d, _)| *d);
+    close.dedup_by_key(|(_, c)| *c);
+
+    close.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+// Levenshtein distance, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Parser;
+    use super::*;
+
+    static INPUT: &str = "autocomplete
+  idle
+    FOCUS -> focused
+  focused
+    match
+    noMatch
+      RETRY -> .matching
+      matching
+    TYPE -> .match
+    BLUR -> #autocomplete.idle";
+
+    #[test]
+    fn resolves_siblings_children_and_ids() {
+        let ast = Parser::new().parse(INPUT).unwrap();
+        let resolve = |source: &[&'static str], target| resolve_target(&ast, source, target);
+
+        assert_eq!(
+            resolve(&["autocomplete", "idle"], "focused"),
+            Ok(vec!["autocomplete", "focused"])
+        );
+        assert_eq!(
+            resolve(&["autocomplete", "idle"], "focused.noMatch.matching"),
+            Ok(vec!["autocomplete", "focused", "noMatch", "matching"])
+        );
+        assert_eq!(
+            resolve(&["autocomplete", "focused"], ".match"),
+            Ok(vec!["autocomplete", "focused", "match"])
+        );
+        assert_eq!(
+            resolve(&["autocomplete", "focused"], "#autocomplete.idle"),
+            Ok(vec!["autocomplete", "idle"])
+        );
+        assert_eq!(
+            resolve(&["autocomplete"], "idle"),
+            Ok(vec!["autocomplete", "idle"])
+        );
+        assert_eq!(
+            resolve(&["autocomplete", "idle"], "#noMatch"),
+            Ok(vec!["autocomplete", "focused", "noMatch"])
+        );
+    }
+
+    #[test]
+    fn reports_where_resolution_failed() {
+        let ast = Parser::new().parse(INPUT).unwrap();
+
+        assert_eq!(
+            resolve_target(&ast, &["autocomplete", "idle"], "focused.mach"),
+            Err(Unresolved {
+                segment: 1,
+                candidates: vec!["match", "noMatch"],
+            })
+        );
+        assert_eq!(
+            resolve_target(&ast, &["autocomplete", "focused", "noMatch"], "#idel"),
+            Err(Unresolved {
+                segment: 0,
+                candidates: vec![
+                    "autocomplete",
+                    "idle",
+                    "focused",
+                    "match",
+                    "noMatch",
+                    "matching"
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let candidates = ["idle", "focused", "match", "noMatch"];
+
+        assert_eq!(suggestions("focussed", &candidates), vec!["focused"]);
+        assert_eq!(suggestions("mach", &candidates), vec!["match"]);
+        assert_eq!(suggestions("xyz", &candidates), Vec::<&str>::new());
+    }
+}
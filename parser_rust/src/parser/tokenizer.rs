@@ -1,4 +1,5 @@
-use regex::Regex;
+use std::collections::VecDeque;
+use std::fmt;
 
 // How do i print my structs and enums?
 // There are 2 ways
@@ -7,7 +8,8 @@ use regex::Regex;
 // below and is used to add some meta data to the program for the compiler.
 // TODO: Use tuple where required to store the text along with token type
 // E.g. Identifier(String);
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[serde(tag = "kind", content = "text")]
 pub enum TokenType<'a> {
     Identifier(&'a str),
     Condition(&'a str),
@@ -19,105 +21,394 @@ pub enum TokenType<'a> {
     ParallelState,
     FinalState,
     InitialState,
+    // a `*` at the start of a line is the event of a transition which is taken
+    // for any event, e.g. `* -> error`. After a state name it's InitialState.
+    Wildcard,
     TransitionArrow,
+    // `~>` and `=>`, a transition which is explicitly internal or external
+    InternalArrow,
+    ExternalArrow,
+    // between the events of a transition, e.g. `BLUR | ESCAPE -> idle`
+    EventSeparator,
+    // between the targets of a transition, e.g. `GO -> #m.a.x, #m.b.y`, and
+    // between arguments, e.g. `> assign(count, 0)`
+    Comma,
+    // `entry` and `exit` followed by actions, e.g. `entry > startTimer`
+    Entry,
+    Exit,
+    // `activities` followed by activity names, e.g. `activities > beeping`
+    Activities,
+    // `H` or `H*` after a state's name
+    History(HistoryType),
+    // `after` followed by a delay, e.g. `after 500ms -> idle`
+    After,
+    // operators in a guard expression, e.g. `; isValid && !(a || b)`. Only
+    // tokenized after a `;`. The names in a guard are Condition tokens.
+    And,
+    Or,
+    Not,
+    // group a guard expression, or hold the arguments of an action or a
+    // condition, e.g. `> log("entered")`
+    LeftParen,
+    RightParen,
+    // a string in double quotes, without the quotes. There are no escapes.
+    String(&'a str),
+    // `invoke` followed by the service to run, optionally with `as` and an
+    // id, e.g. `invoke fetchUser as userLoader`
+    Invoke,
+    As,
+    // something the tokenizer could not make sense of. The parser reports it
+    // with the message from LexError.
+    Error(LexError),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+// A shallow history state remembers which child of its parent was active. A
+// deep one remembers the active states all the way down.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryType {
+    Shallow,
+    Deep,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LexError {
+    // a `;` which is not followed by a condition name
+    MissingConditionName,
+    // a `>` which is not followed by an action name
+    MissingActionName,
+    // a line indented less than the line before it, but not as far back as
+    // any of the enclosing blocks
+    InconsistentDedent,
+    // indentation made of both tabs and spaces
+    MixedIndentation,
+    // a `"` without a closing `"` on the same line
+    UnterminatedString,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LexError::MissingConditionName => "expected a condition name after `;`",
+            LexError::MissingActionName => "expected an action name after `>`",
+            LexError::InconsistentDedent => "dedent does not match any outer indentation level",
+            LexError::MixedIndentation => "indentation mixes tabs and spaces",
+            LexError::UnterminatedString => "string is missing its closing `\"`",
+        })
+    }
+}
+
+// TokenKind is TokenType without the text. The parser uses it to remember
+// which kinds of tokens it was looking for when it failed, so that the error
+// can say "expected identifier or `->`"
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    Identifier,
+    Condition,
+    Indent,
+    Dedent,
+    Unknown,
+    Comment,
+    Action,
+    ParallelState,
+    FinalState,
+    InitialState,
+    Wildcard,
+    TransitionArrow,
+    InternalArrow,
+    ExternalArrow,
+    EventSeparator,
+    Comma,
+    Entry,
+    Exit,
+    Activities,
+    History,
+    After,
+    And,
+    Or,
+    Not,
+    LeftParen,
+    RightParen,
+    String,
+    Invoke,
+    As,
+    // the delay after `after`. It's an identifier token, but only some
+    // identifiers are delays.
+    Delay,
+    // the event of a transition inside an `invoke` block. Also an identifier
+    // token, but only `done` and `error` are allowed.
+    InvokeEvent,
+    // an argument of an action or condition. A string, or an identifier
+    // token which is a number, `true`, `false` or a name.
+    Argument,
+    Error,
+    // not real tokens. The parser expects them wherever a line or the whole
+    // input has to end.
+    EndOfLine,
+    EndOfInput,
+}
+
+impl<'a> TokenType<'a> {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            TokenType::Identifier(_) => TokenKind::Identifier,
+            TokenType::Condition(_) => TokenKind::Condition,
+            TokenType::Indent => TokenKind::Indent,
+            TokenType::Dedent => TokenKind::Dedent,
+            TokenType::Unknown(_) => TokenKind::Unknown,
+            TokenType::Comment(_) => TokenKind::Comment,
+            TokenType::Action(_) => TokenKind::Action,
+            TokenType::ParallelState => TokenKind::ParallelState,
+            TokenType::FinalState => TokenKind::FinalState,
+            TokenType::InitialState => TokenKind::InitialState,
+            TokenType::Wildcard => TokenKind::Wildcard,
+            TokenType::TransitionArrow => TokenKind::TransitionArrow,
+            TokenType::InternalArrow => TokenKind::InternalArrow,
+            TokenType::ExternalArrow => TokenKind::ExternalArrow,
+            TokenType::EventSeparator => TokenKind::EventSeparator,
+            TokenType::Comma => TokenKind::Comma,
+            TokenType::Entry => TokenKind::Entry,
+            TokenType::Exit => TokenKind::Exit,
+            TokenType::Activities => TokenKind::Activities,
+            TokenType::History(_) => TokenKind::History,
+            TokenType::After => TokenKind::After,
+            TokenType::And => TokenKind::And,
+            TokenType::Or => TokenKind::Or,
+            TokenType::Not => TokenKind::Not,
+            TokenType::LeftParen => TokenKind::LeftParen,
+            TokenType::RightParen => TokenKind::RightParen,
+            TokenType::String(_) => TokenKind::String,
+            TokenType::Invoke => TokenKind::Invoke,
+            TokenType::As => TokenKind::As,
+            TokenType::Error(_) => TokenKind::Error,
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TokenKind::Identifier => "identifier",
+            TokenKind::Condition => "condition (`;`)",
+            TokenKind::Indent => "indent",
+            TokenKind::Dedent => "dedent",
+            TokenKind::Unknown => "unknown token",
+            TokenKind::Comment => "comment",
+            TokenKind::Action => "action (`>`)",
+            TokenKind::ParallelState => "`&`",
+            TokenKind::FinalState => "`$`",
+            TokenKind::InitialState => "`*`",
+            TokenKind::Wildcard => "`*` (any event)",
+            TokenKind::TransitionArrow => "`->`",
+            TokenKind::InternalArrow => "`~>`",
+            TokenKind::ExternalArrow => "`=>`",
+            TokenKind::EventSeparator => "`|`",
+            TokenKind::Comma => "`,`",
+            TokenKind::Entry => "`entry`",
+            TokenKind::Exit => "`exit`",
+            TokenKind::Activities => "`activities`",
+            TokenKind::History => "`H`",
+            TokenKind::After => "`after`",
+            TokenKind::And => "`&&`",
+            TokenKind::Or => "`||`",
+            TokenKind::Not => "`!`",
+            TokenKind::LeftParen => "`(`",
+            TokenKind::RightParen => "`)`",
+            TokenKind::String => "string",
+            TokenKind::Argument => "argument (a string, number, `true`, `false` or a name)",
+            TokenKind::Delay => "delay (like `500ms`, `2s`, `1m` or a name)",
+            TokenKind::Invoke => "`invoke`",
+            TokenKind::As => "`as`",
+            TokenKind::InvokeEvent => "`done` or `error`",
+            TokenKind::Error => "error",
+            TokenKind::EndOfLine => "end of line",
+            TokenKind::EndOfInput => "end of input",
+        })
+    }
+}
+
+// how a token is shown to the user in an error message
+impl<'a> fmt::Display for TokenType<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenType::Identifier(text) => write!(f, "identifier `{}`", text),
+            TokenType::Condition(text) => write!(f, "condition `{}`", text),
+            TokenType::Action(text) => write!(f, "action `{}`", text),
+            TokenType::Unknown(text) => write!(f, "unknown token `{}`", text),
+            TokenType::Comment(_) => f.write_str("comment"),
+            TokenType::Error(error) => error.fmt(f),
+            typ => typ.kind().fmt(f),
+        }
+    }
+}
+
+// line_number and col are zero based. start and end are byte offsets into the
+// whole input string, so that the editor can highlight the exact range
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
 pub struct Position {
-    line_number: usize,
-    col: usize,
+    pub line_number: usize,
+    pub col: usize,
+    pub start: usize,
+    pub end: usize,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 pub struct Token<'a> {
     pub typ: TokenType<'a>,
     pub pos: Position,
 }
 
-// Instead of having a Token type with line and col, maybe it's better to rename
-// TokenType to Token, convert the lexer to an iterator where the parser keeps
-// asking for the next token. And when the parser needs it, most probably during
-// an error, the parser can ask the lexer for the current line and column
-// It will also make our lexer more performant because it will not go through
-// the whole text and get all tokens. It will do so lazily. In most cases when
-// there's an error in the initial parts of the string or in the middle, it
-// won't waste time parsing the rest of the string.
-
-fn comment_token(line_number: usize, offset: usize, input: &str) -> Token {
+fn comment_token(line_number: usize, offset: usize, input: &str) -> Token<'_> {
     let text = &input[offset..];
 
-    get_token(line_number, offset, TokenType::Comment(text))
+    get_token(
+        line_number,
+        input,
+        offset,
+        text.len(),
+        TokenType::Comment(text),
+    )
 }
 
-// TODO: move the code to get identifier text to another function
-fn condition_token(line_number: usize, mut offset: usize, input: &str) -> (usize, Token) {
-    let input_as_chars: Vec<char> = input.chars().collect();
+// the operators which can appear in a guard expression, along with their
+// length in bytes
+fn guard_operator(rest: &str) -> Option<(usize, TokenType<'static>)> {
+    if rest.starts_with("&&") {
+        Some((2, TokenType::And))
+    } else if rest.starts_with("||") {
+        Some((2, TokenType::Or))
+    } else {
+        match rest.chars().next()? {
+            '!' => Some((1, TokenType::Not)),
+            _ => None,
+        }
+    }
+}
 
-    let mut c = input_as_chars[offset];
+// `"some text"`. Everything up to the next `"` on the line is the string.
+fn string_token(line_number: usize, offset: usize, input: &str) -> (usize, Token<'_>) {
+    match input[offset + 1..].find('"') {
+        Some(len) => (
+            offset + len + 2,
+            get_token(
+                line_number,
+                input,
+                offset,
+                len + 2,
+                TokenType::String(&input[offset + 1..offset + 1 + len]),
+            ),
+        ),
+        None => (
+            input.len(),
+            get_token(
+                line_number,
+                input,
+                offset,
+                input.len() - offset,
+                TokenType::Error(LexError::UnterminatedString),
+            ),
+        ),
+    }
+}
 
-    while !is_identifier_start(c) {
-        c = input_as_chars[offset];
-        offset += 1;
+// an action token is a `>` followed by a name. The token sits at the name.
+// If there is no name after the `>`, we return an error token covering the
+// `>` instead.
+fn action_token(line_number: usize, offset: usize, input: &str) -> (usize, Token<'_>) {
+    // skip the `>` and any whitespace after it
+    let mut name_offset = offset + 1;
+    while let Some(c) = input[name_offset..]
+        .chars()
+        .next()
+        .filter(|c| c.is_whitespace())
+    {
+        name_offset += c.len_utf8();
     }
-    offset -= 1;
-    let identifier = identifier_token(line_number, offset, input);
 
-    let text = match identifier.typ {
-        TokenType::Identifier(t) => t,
-        _ => " ",
-    };
+    if input[name_offset..].starts_with(is_identifier_char) {
+        let identifier = identifier_token(line_number, name_offset, input);
+
+        let text = match identifier.typ {
+            TokenType::Identifier(t) => t,
+            _ => " ",
+        };
+
+        return (
+            name_offset + text.len(),
+            get_token(
+                line_number,
+                input,
+                name_offset,
+                text.len(),
+                TokenType::Action(text),
+            ),
+        );
+    }
 
-    // TODO: The offset sent to get_token is wrong here. We are mutating it.
     (
-        offset + text.len(),
-        get_token(line_number, offset, TokenType::Condition(text)),
+        offset + 1,
+        get_token(
+            line_number,
+            input,
+            offset,
+            1,
+            TokenType::Error(LexError::MissingActionName),
+        ),
     )
 }
 
-fn action_token(line_number: usize, mut offset: usize, input: &str) -> (usize, Token) {
-    let input_as_chars: Vec<char> = input.chars().collect();
-
-    let mut c = input_as_chars[offset];
+fn identifier_token(line_number: usize, offset: usize, input: &str) -> Token<'_> {
+    let rest = &input[offset..];
+    let len = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+    let text = &rest[..len];
 
-    while !is_identifier_start(c) {
-        c = input_as_chars[offset];
-        offset += 1;
-    }
-    offset -= 1;
-    let identifier = identifier_token(line_number, offset, input);
-
-    let text = match identifier.typ {
-        TokenType::Identifier(t) => t,
-        _ => " ",
-    };
-
-    // TODO: The offset sent to get_token is wrong here. We are mutating it.
-    (
-        offset + text.len(),
-        get_token(line_number, offset, TokenType::Action(text)),
+    get_token(
+        line_number,
+        input,
+        offset,
+        text.len(),
+        TokenType::Identifier(text),
     )
 }
 
-fn identifier_token(line_number: usize, offset: usize, input: &str) -> Token {
-    let text = &input[offset..]
-        .split(|c| is_identifier_start(c) == false)
-        .collect::<Vec<&str>>()[0];
+// Keywords are only keywords where nothing else would make sense, so they
+// can still be used as names of states and events. `entry`, `exit` and
+// `activities` are keywords when they start a line and `>` follows them. `H`
+// is one right after a state's name, and `after` is one when a delay follows
+// it. `invoke` starts a line and is followed by a name, and so is the `as`
+// after it. `rest` is the line after the word and `line_so_far` the tokens
+// before it.
+fn keyword<'a>(text: &str, rest: &str, line_so_far: &[TokenKind]) -> Option<TokenType<'a>> {
+    // not in `GO -> entry > log`, where `entry` is the target
+    let followed_by_action = line_so_far.is_empty() && rest.trim_start().starts_with('>');
+    let followed_by_name =
+        rest.starts_with(char::is_whitespace) && rest.trim_start().starts_with(is_identifier_char);
+    let after_state_name = line_so_far == [TokenKind::Identifier];
 
-    get_token(line_number, offset, TokenType::Identifier(text))
+    match text {
+        "entry" if followed_by_action => Some(TokenType::Entry),
+        "exit" if followed_by_action => Some(TokenType::Exit),
+        "activities" if followed_by_action => Some(TokenType::Activities),
+        "after" if followed_by_name => Some(TokenType::After),
+        "invoke" if followed_by_name && line_so_far.is_empty() => Some(TokenType::Invoke),
+        "as" if followed_by_name && line_so_far == [TokenKind::Invoke, TokenKind::Identifier] => {
+            Some(TokenType::As)
+        }
+        "H" if after_state_name && rest.starts_with('*') => {
+            Some(TokenType::History(HistoryType::Deep))
+        }
+        "H" if after_state_name => Some(TokenType::History(HistoryType::Shallow)),
+        _ => None,
+    }
 }
 
-fn is_identifier_start(c: char) -> bool {
-    // How do i use regex in rust?
-    // rust does not support regular expressions (regex) out of the box
-    // We have to use an external library
-    let re = Regex::new(r"^[#a-zA-Z0-9_\.]").unwrap();
-
-    // How do i convert character to string?
-    // is_match method expects a string
-    // Ans -> use `to_string()`
-    // But to_string returns a String, but we want a &str
-    // How do i convert String to &str?
-    re.is_match(&c.to_string()[..])
+// Identifiers are made of ascii letters, digits, `#`, `_` and `.`. This used
+// to be a regex compiled for every character we looked at, which made
+// tokenizing big sketches slow.
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '#' || c == '_' || c == '.'
 }
 
 // this is the key function in the tokenizer
@@ -128,199 +419,449 @@ fn is_identifier_start(c: char) -> bool {
 fn indent_dedent_tokens<'a>(
     line_number: usize,
     indent_stack: &mut Vec<usize>,
-    line: &Vec<char>,
+    tab_width: usize,
+    line: &'a str,
 ) -> (usize, Vec<Token<'a>>) {
-    let mut offset = 0;
-    let mut current_indent_level: usize = 0;
+    // spaces and tabs are one byte long, so the length of the indentation is
+    // also the byte offset of the first character after it
+    let indentation = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+    let offset = indentation.len();
     let mut tokens: Vec<Token> = Vec::new();
 
-    while line[offset] == ' ' {
-        current_indent_level += 1;
-        offset += 1;
+    // blank lines and comments don't open or close blocks, wherever they
+    // start
+    if line[offset..].trim().is_empty() || line[offset..].starts_with('%') {
+        return (offset, tokens);
     }
 
-    if current_indent_level > 0 {
-        match indent_stack.last() {
-            None => {
-                // it's the first indent we have encountered
-                // or - all indents have been deindented
+    // a tab moves to the next multiple of tab_width, like it does in editors
+    let current_indent_level = indentation.chars().fold(0, |level, c| match c {
+        '\t' => (level / tab_width + 1) * tab_width,
+        _ => level + 1,
+    });
+
+    match indent_stack.last() {
+        None => {
+            // it's the first indent we have encountered
+            // or - all indents have been deindented
+            if current_indent_level > 0 {
                 indent_stack.push(current_indent_level);
-                tokens.push(get_token(line_number, offset, TokenType::Indent));
+                tokens.push(get_token(line_number, line, offset, 0, TokenType::Indent));
             }
-            Some(&prev_indent_level) => {
-                if prev_indent_level < current_indent_level {
-                    indent_stack.push(current_indent_level);
-                    tokens.push(get_token(line_number, offset, TokenType::Indent));
-                } else if prev_indent_level > current_indent_level {
-                    // TODO: we should implement some syntax error checking
-                    // in this part. E.g. previous indent level is 2 and the
-                    // current one is 6. It's too much.
-                    // Or the one below
-                    // const dedentLevelInStack = indentStack.find(
-                    // (n) => n === currentIndentLevel,
-                    // );
-
-                    // // any dedent/outdent must match some previous indentation level.
-                    // // otherwise it's a syntax error
-                    // if (dedentLevelInStack === undefined) {
-                    // throw new Error('Invalid indentation');
-                    // }
-
-                    while indent_stack.len() > 0 {
-                        let prev_indent = indent_stack.pop().unwrap();
-                        // keep popping indentation levels from indent dedentLevelInStack
-                        // until we reach the current indent level
-                        // push those many dedent tokens to tokenizer
-                        if prev_indent > current_indent_level {
-                            tokens.push(get_token(line_number, offset, TokenType::Dedent));
-                        } else {
-                            indent_stack.push(prev_indent);
-                            break;
-                        }
+        }
+        Some(&prev_indent_level) => {
+            if prev_indent_level < current_indent_level {
+                indent_stack.push(current_indent_level);
+                tokens.push(get_token(line_number, line, offset, 0, TokenType::Indent));
+            } else if prev_indent_level > current_indent_level {
+                while let Some(prev_indent) = indent_stack.pop() {
+                    // keep popping indentation levels from indent dedentLevelInStack
+                    // until we reach the current indent level
+                    // push those many dedent tokens to tokenizer
+                    if prev_indent > current_indent_level {
+                        tokens.push(get_token(line_number, line, offset, 0, TokenType::Dedent));
+                    } else {
+                        indent_stack.push(prev_indent);
+                        break;
                     }
                 }
+
+                // any dedent must go back to the level of some outer block.
+                // Otherwise we can't tell which block the line belongs to.
+                if indent_stack.last().copied().unwrap_or(0) != current_indent_level {
+                    tokens.push(get_token(
+                        line_number,
+                        line,
+                        0,
+                        offset,
+                        TokenType::Error(LexError::InconsistentDedent),
+                    ));
+                }
             }
         }
     }
 
+    // with tabs and spaces on the same line, the level depends on the tab
+    // width of whoever wrote it. Better to ask than to guess.
+    if indentation.contains(' ') && indentation.contains('\t') {
+        tokens.push(get_token(
+            line_number,
+            line,
+            0,
+            offset,
+            TokenType::Error(LexError::MixedIndentation),
+        ));
+    }
+
     (offset, tokens)
 }
 
-fn get_token<'a>(line_number: usize, col: usize, typ: TokenType<'a>) -> Token<'a> {
+// offset and len are in bytes. col is in characters, which is what the
+// editor counts in. start and end are relative to the beginning of the line
+// here. tokenize moves them to be relative to the whole input once the line
+// is done.
+fn get_token<'a>(
+    line_number: usize,
+    line: &str,
+    offset: usize,
+    len: usize,
+    typ: TokenType<'a>,
+) -> Token<'a> {
     Token {
-        pos: Position { line_number, col },
+        pos: Position {
+            line_number,
+            col: line[..offset].chars().count(),
+            start: offset,
+            end: offset + len,
+        },
         typ,
     }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    // How to write a comment in rust. Like we do in javascript.
-    // Rust comments are more than comments though. We can write whole tests
-    // inside a comment for a function.
-
-    // How to split a string into lines? using split function. But it's not
-    // that simple. `split` return an iterator and if we want the lines as
-    // as vector or array, we have to use the collect method of the iterator
-    // The syntax for collect gets weird when we want to tell it the type to
-    // be returned.
-    // let lines =  input.split("\n").collect<Vec<&str>>();
-    // Or we can annotate the variable to which the value of
-    // we can avoid specifying it in that weird way in collect by specifying
-    // type of lines
-    let lines: Vec<&str> = input.split("\n").collect();
-    // How to create an empty vector?
-    let mut tokens: Vec<Token> = Vec::new();
-    // line and col keep track of the current line and col number
+// Text pasted from some editors starts with a byte order mark. It is not
+// part of the sketch, so the first line starts after it. Positions are still
+// byte offsets into the input as it was given to us.
+fn first_line_start(input: &str) -> usize {
+    if input.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    }
+}
+
+// Returns the line which starts at byte offset `start`, without its line
+// break, and where the next line starts if there is one. Lines can end with
+// `\n`, `\r\n` (windows) or a lone `\r` (old macs).
+fn line_at(input: &str, start: usize) -> (&str, Option<usize>) {
+    let rest = &input[start..];
+
+    match rest.find(['\n', '\r']) {
+        Some(i) if rest[i..].starts_with("\r\n") => (&rest[..i], Some(start + i + 2)),
+        Some(i) => (&rest[..i], Some(start + i + 1)),
+        None => (rest, None),
+    }
+}
+
+// position just after the last character of the input. Used for tokens
+// generated at the end of input and for "unexpected end of input" errors.
+pub fn end_position(input: &str) -> Position {
     let mut line_number = 0;
-    // offset keeps track of the current character position in the line
-    let mut offset;
-    let mut indent_stack: Vec<usize> = Vec::new();
-
-    // TODO: can we write it as input.split("\n").map().flatten().collect()?
-    // The map function returns the list of tokens in one line
-
-    // writing `for line in lines` would mean moving lines inside the for block
-    // and hence not being available outside it
-    for line in &lines {
-        // how to convert a string into a list of characters? Use chars method
-        // on string. Again, chars returns an iterator instead of a vector.
-        // This seems to be a common pattern. Whenever a javascript programmer
-        // expects an array of something, rust functions/methods return an
-        // iterator.
-        // Probably my tokenize function should also return an iterator of
-        // Tokens instead of a Vector of tokens
-        let char_vec: Vec<char> = line.chars().collect();
-
-        let (new_offset, indent_tokens) =
-            indent_dedent_tokens(line_number, &mut indent_stack, &char_vec);
-        offset = new_offset;
+    let mut line_start = first_line_start(input);
+
+    while let (_, Some(next_line_start)) = line_at(input, line_start) {
+        line_number += 1;
+        line_start = next_line_start;
+    }
+
+    Position {
+        line_number,
+        col: input[line_start..].chars().count(),
+        start: input.len(),
+        end: input.len(),
+    }
+}
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+// The lexer is an iterator of tokens. It tokenizes the input one line at a
+// time, and only when somebody asks for a token from the next line. So if
+// the parser gives up at the top of a long sketch, the rest of the sketch is
+// never tokenized. It works on the input string directly, line by line,
+// without collecting the lines or characters into vectors.
+pub struct Lexer<'a> {
+    input: &'a str,
+    tab_width: usize,
+    indent_stack: Vec<usize>,
+    // line number and byte offset of the beginning of the next line in the
+    // input. None once all the lines have been tokenized.
+    line_number: usize,
+    line_start: Option<usize>,
+    // tokens of the current line which have not been handed out yet
+    pending: VecDeque<Token<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer::with_tab_width(input, DEFAULT_TAB_WIDTH)
+    }
+
+    pub fn with_tab_width(input: &'a str, tab_width: usize) -> Lexer<'a> {
+        Lexer {
+            input,
+            // a tab has to move the indentation forward
+            tab_width: tab_width.max(1),
+            indent_stack: Vec::new(),
+            line_number: 0,
+            line_start: Some(first_line_start(input)),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn tokenize_line(&mut self, line_number: usize, line_start: usize, line: &'a str) {
+        let (mut offset, indent_tokens) =
+            indent_dedent_tokens(line_number, &mut self.indent_stack, self.tab_width, line);
 
         // extend extends a collection with contents of an iterator
-        tokens.extend(indent_tokens);
+        self.pending.extend(indent_tokens);
+        let line_tokens_start = self.pending.len();
+        // true between a `;` and the actions after it
+        let mut in_guard = false;
+        // true inside the parentheses right after an action or condition
+        // name. Names in there are arguments, never keywords or conditions.
+        let mut in_args = false;
+
+        // offset is a byte offset into the line. We can't index a str by
+        // character, so we look at the first character of what's left of
+        // the line and move ahead by as many bytes as that character takes.
+        while let Some(c) = line[offset..].chars().next() {
+            if let Some((len, typ)) =
+                guard_operator(&line[offset..]).filter(|_| in_guard && !in_args)
+            {
+                self.pending
+                    .push_back(get_token(line_number, line, offset, len, typ));
+                offset += len;
+                continue;
+            }
 
-        // why can we split the char_vec at offset and then iterate on the line
-        // from that point?
-        // Because on every loop the offset changes by more than or equal to 1
-        while offset < char_vec.len() {
-            let c = char_vec[offset];
             match c {
                 // How to create new values of a struct?
                 '%' => {
-                    tokens.push(comment_token(line_number, offset, line));
+                    self.pending
+                        .push_back(comment_token(line_number, offset, line));
                     break;
                 }
                 '&' => {
-                    tokens.push(get_token(line_number, offset, TokenType::ParallelState));
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::ParallelState,
+                    ));
                     offset += 1;
                 }
                 '$' => {
-                    tokens.push(get_token(line_number, offset, TokenType::FinalState));
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::FinalState,
+                    ));
                     offset += 1;
                 }
                 '*' => {
-                    tokens.push(get_token(line_number, offset, TokenType::InitialState));
+                    // a state name always comes before its markers
+                    let typ = if self.pending.len() == line_tokens_start {
+                        TokenType::Wildcard
+                    } else {
+                        TokenType::InitialState
+                    };
+                    self.pending
+                        .push_back(get_token(line_number, line, offset, 1, typ));
                     offset += 1;
                 }
+                // the `;` itself is not a token. The guard after it is.
                 ';' => {
-                    let (new_offset, condition) = condition_token(line_number, offset, line);
-                    offset = new_offset;
-                    tokens.push(condition);
-                }
-                '-' => {
-                    if offset < line.len() - 1 && char_vec[offset + 1] == '>' {
-                        tokens.push(get_token(line_number, offset, TokenType::TransitionArrow));
-                        offset += 2;
+                    let guard = line[offset + 1..].trim_start();
+                    if guard.starts_with(|c| is_identifier_char(c) || c == '(')
+                        || guard_operator(guard).is_some()
+                    {
+                        in_guard = true;
                     } else {
-                        tokens.push(get_token(
+                        self.pending.push_back(get_token(
                             line_number,
+                            line,
                             offset,
-                            TokenType::Unknown("unknown"),
+                            1,
+                            TokenType::Error(LexError::MissingConditionName),
                         ));
-                        offset += 1;
                     }
+                    offset += 1;
+                }
+                // a `-` which is not followed by `>` is an unknown token
+                '-' if line[offset..].starts_with("->") => {
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        2,
+                        TokenType::TransitionArrow,
+                    ));
+                    offset += 2;
+                }
+                '~' | '=' if line[offset + 1..].starts_with('>') => {
+                    let typ = match c {
+                        '~' => TokenType::InternalArrow,
+                        _ => TokenType::ExternalArrow,
+                    };
+                    self.pending
+                        .push_back(get_token(line_number, line, offset, 2, typ));
+                    offset += 2;
+                }
+                '(' => {
+                    in_args = matches!(
+                        self.pending.back(),
+                        Some(Token {
+                            typ: TokenType::Action(_) | TokenType::Condition(_),
+                            pos,
+                        }) if pos.end == offset
+                    );
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::LeftParen,
+                    ));
+                    offset += 1;
+                }
+                ')' => {
+                    in_args = false;
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::RightParen,
+                    ));
+                    offset += 1;
+                }
+                '"' => {
+                    let (new_offset, string) = string_token(line_number, offset, line);
+                    offset = new_offset;
+                    self.pending.push_back(string);
+                }
+                // a negative number argument, e.g. `> add(-1)`
+                '-' if in_args && line[offset + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    let number = identifier_token(line_number, offset + 1, line);
+                    let len = number.pos.end - offset;
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        len,
+                        TokenType::Identifier(&line[offset..offset + len]),
+                    ));
+                    offset += len;
+                }
+                // `||` in a guard is handled above
+                '|' => {
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::EventSeparator,
+                    ));
+                    offset += 1;
+                }
+                ',' => {
+                    self.pending.push_back(get_token(
+                        line_number,
+                        line,
+                        offset,
+                        1,
+                        TokenType::Comma,
+                    ));
+                    offset += 1;
                 }
                 '>' => {
+                    in_guard = false;
                     let (new_offset, condition) = action_token(line_number, offset, line);
                     offset = new_offset;
-                    tokens.push(condition);
+                    self.pending.push_back(condition);
                 }
-                c if is_identifier_start(c) => {
-                    let identifier = identifier_token(line_number, offset, line);
+                c if is_identifier_char(c) => {
+                    let mut identifier = identifier_token(line_number, offset, line);
                     let text = match identifier.typ {
                         TokenType::Identifier(t) => t,
                         _ => " ",
                     };
                     offset += text.len();
-                    tokens.push(identifier);
+
+                    let line_so_far: Vec<TokenKind> = self
+                        .pending
+                        .range(line_tokens_start..)
+                        .map(|t| t.typ.kind())
+                        .collect();
+                    if in_args {
+                        // arguments stay identifiers
+                    } else if in_guard {
+                        identifier.typ = TokenType::Condition(text);
+                    } else if let Some(typ) = keyword(text, &line[offset..], &line_so_far) {
+                        // the `*` of `H*` is part of the token
+                        if typ == TokenType::History(HistoryType::Deep) {
+                            offset += 1;
+                            identifier.pos.end += 1;
+                        }
+                        identifier.typ = typ;
+                    }
+                    self.pending.push_back(identifier);
                 }
-                c if c.is_whitespace() => offset += 1,
-                _ => {
-                    tokens.push(get_token(
+                c if c.is_whitespace() => offset += c.len_utf8(),
+                c => {
+                    let text = &line[offset..offset + c.len_utf8()];
+                    self.pending.push_back(get_token(
                         line_number,
+                        line,
                         offset,
-                        TokenType::Unknown("unknown"),
+                        text.len(),
+                        TokenType::Unknown(text),
                     ));
-                    offset += 1;
+                    offset += text.len();
                 }
             }
         }
 
-        line_number += 1;
+        for token in &mut self.pending {
+            token.pos.start += line_start;
+            token.pos.end += line_start;
+        }
     }
+}
 
-    // pop out all the Dedents
-    while indent_stack.len() > 0 {
-        indent_stack.pop();
-        tokens.push(get_token(line_number, 0, TokenType::Dedent))
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        // blank lines have no tokens. Keep going until we find a line which
+        // does.
+        while self.pending.is_empty() {
+            match self.line_start {
+                Some(line_start) => {
+                    let (line, next_line_start) = line_at(self.input, line_start);
+                    self.tokenize_line(self.line_number, line_start, line);
+                    self.line_number += 1;
+                    self.line_start = next_line_start;
+                }
+                None => {
+                    // pop out all the Dedents
+                    self.indent_stack.pop()?;
+                    return Some(Token {
+                        typ: TokenType::Dedent,
+                        pos: end_position(self.input),
+                    });
+                }
+            }
+        }
+
+        self.pending.pop_front()
     }
+}
 
-    // println!("tokens: {:?}", tokens.len());
-    tokens
+pub fn tokenize(input: &str) -> Vec<Token<'_>> {
+    Lexer::new(input).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    fn types(input: &str) -> Vec<TokenType<'_>> {
+        tokenize(input).into_iter().map(|t| t.typ).collect()
+    }
 
     static INPUT: &str = "abc
 % some comment
@@ -382,8 +923,10 @@ mod tests {
             TokenType::Identifier("that"),
             TokenType::Action("andDoThis"),
             TokenType::Identifier("lastState"),
-            TokenType::Indent,
+            // comment lines don't affect indentation. The indent comes with
+            // the next line.
             TokenType::Comment("% trying out transient state"),
+            TokenType::Indent,
             TokenType::TransitionArrow,
             TokenType::Identifier("ast"),
             TokenType::Condition("ifyes"),
@@ -407,4 +950,464 @@ mod tests {
             i += 1;
         }
     }
+
+    #[test]
+    fn token_positions() {
+        let tokens = tokenize("abc\n  def -> lmn");
+
+        assert_eq!(
+            tokens[2],
+            Token {
+                typ: TokenType::Identifier("def"),
+                pos: Position {
+                    line_number: 1,
+                    col: 2,
+                    start: 6,
+                    end: 9,
+                },
+            }
+        );
+        assert_eq!(
+            tokens[3].pos,
+            Position {
+                line_number: 1,
+                col: 6,
+                start: 10,
+                end: 12,
+            }
+        );
+        // dedents generated at the end sit at the end of input
+        assert_eq!(
+            tokens[5].pos,
+            Position {
+                line_number: 1,
+                col: 12,
+                start: 16,
+                end: 16,
+            }
+        );
+    }
+
+    #[test]
+    fn dedent_to_first_column() {
+        let tokens = types("abc\n  def\n% comment\nxyz");
+
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::Identifier("abc"),
+                TokenType::Indent,
+                TokenType::Identifier("def"),
+                TokenType::Comment("% comment"),
+                TokenType::Dedent,
+                TokenType::Identifier("xyz"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let tokens = types("abc\n\n  def\n    \n\t\n  ghi\n");
+
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::Identifier("abc"),
+                TokenType::Indent,
+                TokenType::Identifier("def"),
+                TokenType::Identifier("ghi"),
+                TokenType::Dedent,
+            ]
+        );
+    }
+
+    #[test]
+    fn marker_without_name_is_an_error_token() {
+        let tokens = tokenize("a -> b;\nc -> d >   \ne -> f ; %comment");
+
+        assert_eq!(
+            tokens[3],
+            Token {
+                typ: TokenType::Error(LexError::MissingConditionName),
+                pos: Position {
+                    line_number: 0,
+                    col: 6,
+                    start: 6,
+                    end: 7,
+                },
+            }
+        );
+        assert_eq!(tokens[7].typ, TokenType::Error(LexError::MissingActionName));
+        assert_eq!(
+            tokens[11].typ,
+            TokenType::Error(LexError::MissingConditionName)
+        );
+        assert_eq!(tokens[12].typ, TokenType::Comment("%comment"));
+    }
+
+    proptest! {
+        #[test]
+        fn tokenize_never_panics(input in "(\\PC|\\s)*") {
+            tokenize(&input);
+        }
+    }
+
+    #[test]
+    fn unicode_positions() {
+        let input = "é cafe % ünïcödé\n  naïve -> x; ok";
+        let tokens = tokenize(input);
+
+        // `é` is not allowed in identifiers
+        assert_eq!(
+            tokens[0],
+            Token {
+                typ: TokenType::Unknown("é"),
+                pos: Position {
+                    line_number: 0,
+                    col: 0,
+                    start: 0,
+                    end: 2,
+                },
+            }
+        );
+        assert_eq!(
+            tokens[1],
+            Token {
+                typ: TokenType::Identifier("cafe"),
+                pos: Position {
+                    line_number: 0,
+                    col: 2,
+                    start: 3,
+                    end: 7,
+                },
+            }
+        );
+        assert_eq!(tokens[2].typ, TokenType::Comment("% ünïcödé"));
+        assert_eq!(tokens[2].pos.col, 7);
+
+        let types: Vec<TokenType> = tokens[3..].iter().map(|t| t.typ.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Indent,
+                TokenType::Identifier("na"),
+                TokenType::Unknown("ï"),
+                TokenType::Identifier("ve"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("x"),
+                TokenType::Condition("ok"),
+                TokenType::Dedent,
+            ]
+        );
+        assert_eq!(tokens[6].pos.col, 5);
+        assert_eq!(tokens[9].pos.col, 14);
+
+        // every span points at the token's own text in the original input
+        for token in &tokens {
+            let text = &input[token.pos.start..token.pos.end];
+            match token.typ {
+                TokenType::Identifier(t)
+                | TokenType::Unknown(t)
+                | TokenType::Comment(t)
+                | TokenType::Condition(t) => assert_eq!(text, t),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_indentation_is_an_error() {
+        let tokens = tokenize("abc\n  def\n \tghi");
+
+        assert_eq!(tokens[3].typ, TokenType::Indent);
+        assert_eq!(
+            tokens[4],
+            Token {
+                typ: TokenType::Error(LexError::MixedIndentation),
+                pos: Position {
+                    line_number: 2,
+                    col: 0,
+                    start: 10,
+                    end: 12,
+                },
+            }
+        );
+        assert_eq!(tokens[5].typ, TokenType::Identifier("ghi"));
+    }
+
+    #[test]
+    fn tabs_count_to_the_next_tab_stop() {
+        let types =
+            |input| -> Vec<TokenType> { Lexer::with_tab_width(input, 2).map(|t| t.typ).collect() };
+
+        // the tab takes `ghi` to column 2, same as `def`
+        assert_eq!(
+            types("abc\n  def\n\tghi"),
+            vec![
+                TokenType::Identifier("abc"),
+                TokenType::Indent,
+                TokenType::Identifier("def"),
+                TokenType::Identifier("ghi"),
+                TokenType::Dedent,
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_and_lone_cr_end_lines() {
+        let expected = types("abc\n  def\nghi");
+
+        assert_eq!(types("abc\r\n  def\r\nghi"), expected);
+        assert_eq!(types("abc\r  def\rghi"), expected);
+
+        // positions still point into the original text, `\r` included
+        let tokens = tokenize("abc\r\n  def\r\n");
+        assert_eq!(
+            tokens[2].pos,
+            Position {
+                line_number: 1,
+                col: 2,
+                start: 7,
+                end: 10,
+            }
+        );
+        assert_eq!(end_position("abc\r\n  def\r\n").line_number, 2);
+        assert_eq!(end_position("abc\r\n  def\r\n").col, 0);
+    }
+
+    #[test]
+    fn leading_bom_is_skipped() {
+        let tokens = tokenize("\u{feff}abc -> def");
+
+        assert_eq!(tokens[0].typ, TokenType::Identifier("abc"));
+        assert_eq!(
+            tokens[0].pos,
+            Position {
+                line_number: 0,
+                col: 0,
+                start: 3,
+                end: 6,
+            }
+        );
+        assert_eq!(end_position("\u{feff}abc").col, 3);
+    }
+
+    #[test]
+    fn keywords_before_actions() {
+        assert_eq!(
+            types("entry > a > b\nexit> c\nactivities > d"),
+            vec![
+                TokenType::Entry,
+                TokenType::Action("a"),
+                TokenType::Action("b"),
+                TokenType::Exit,
+                TokenType::Action("c"),
+                TokenType::Activities,
+                TokenType::Action("d"),
+            ]
+        );
+        // anywhere else they are plain identifiers
+        assert_eq!(
+            types("entry -> exit"),
+            vec![
+                TokenType::Identifier("entry"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("exit"),
+            ]
+        );
+        assert_eq!(
+            types("GO -> entry > log\nSTOP -> exit > log\nRING -> activities > log"),
+            vec![
+                TokenType::Identifier("GO"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("entry"),
+                TokenType::Action("log"),
+                TokenType::Identifier("STOP"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("exit"),
+                TokenType::Action("log"),
+                TokenType::Identifier("RING"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("activities"),
+                TokenType::Action("log"),
+            ]
+        );
+    }
+
+    #[test]
+    fn history_markers() {
+        assert_eq!(
+            types("hist H\ndeep H* -> idle\nH\nGO -> H"),
+            vec![
+                TokenType::Identifier("hist"),
+                TokenType::History(HistoryType::Shallow),
+                TokenType::Identifier("deep"),
+                TokenType::History(HistoryType::Deep),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("idle"),
+                // a state called `H`, and a transition to it
+                TokenType::Identifier("H"),
+                TokenType::Identifier("GO"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("H"),
+            ]
+        );
+
+        let tokens = tokenize("deep H*");
+        assert_eq!((tokens[1].pos.start, tokens[1].pos.end), (5, 7));
+    }
+
+    #[test]
+    fn after_is_a_keyword_before_a_delay() {
+        assert_eq!(
+            types("after 500ms -> idle\nafter -> idle"),
+            vec![
+                TokenType::After,
+                TokenType::Identifier("500ms"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("idle"),
+                TokenType::Identifier("after"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("idle"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invoke_and_as_keywords() {
+        assert_eq!(
+            types("invoke fetchUser as loader\ninvoke -> as"),
+            vec![
+                TokenType::Invoke,
+                TokenType::Identifier("fetchUser"),
+                TokenType::As,
+                TokenType::Identifier("loader"),
+                TokenType::Identifier("invoke"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("as"),
+            ]
+        );
+    }
+
+    #[test]
+    fn guard_expressions() {
+        let tokens = tokenize("a -> b; isValid && !(isLocked || busy) > log");
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.typ.clone()).collect();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier("a"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("b"),
+                TokenType::Condition("isValid"),
+                TokenType::And,
+                TokenType::Not,
+                TokenType::LeftParen,
+                TokenType::Condition("isLocked"),
+                TokenType::Or,
+                TokenType::Condition("busy"),
+                TokenType::RightParen,
+                TokenType::Action("log"),
+            ]
+        );
+        assert_eq!((tokens[4].pos.start, tokens[4].pos.end), (16, 18));
+
+        // outside of a guard `&` is still the parallel state marker
+        assert_eq!(tokenize("a&")[1].typ, TokenType::ParallelState);
+    }
+
+    #[test]
+    fn event_and_target_separators() {
+        let types = types("BLUR | ESCAPE -> idle, #m.b; a || b");
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier("BLUR"),
+                TokenType::EventSeparator,
+                TokenType::Identifier("ESCAPE"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("idle"),
+                TokenType::Comma,
+                TokenType::Identifier("#m.b"),
+                TokenType::Condition("a"),
+                TokenType::Or,
+                TokenType::Condition("b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn internal_and_external_arrows() {
+        let types = types("A ~> .b\nB => c\nC = > d");
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier("A"),
+                TokenType::InternalArrow,
+                TokenType::Identifier(".b"),
+                TokenType::Identifier("B"),
+                TokenType::ExternalArrow,
+                TokenType::Identifier("c"),
+                TokenType::Identifier("C"),
+                TokenType::Unknown("="),
+                TokenType::Action("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcard_at_the_start_of_a_line() {
+        let types = types("abc*\n  * -> error");
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier("abc"),
+                TokenType::InitialState,
+                TokenType::Indent,
+                TokenType::Wildcard,
+                TokenType::TransitionArrow,
+                TokenType::Identifier("error"),
+                TokenType::Dedent,
+            ]
+        );
+    }
+
+    #[test]
+    fn arguments_and_strings() {
+        let types = types("GO -> a; hasRole(\"admin\") && (b) > add(after, -1)");
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier("GO"),
+                TokenType::TransitionArrow,
+                TokenType::Identifier("a"),
+                TokenType::Condition("hasRole"),
+                TokenType::LeftParen,
+                TokenType::String("admin"),
+                TokenType::RightParen,
+                TokenType::And,
+                TokenType::LeftParen,
+                TokenType::Condition("b"),
+                TokenType::RightParen,
+                TokenType::Action("add"),
+                TokenType::LeftParen,
+                TokenType::Identifier("after"),
+                TokenType::Comma,
+                TokenType::Identifier("-1"),
+                TokenType::RightParen,
+            ]
+        );
+
+        let tokens = tokenize("> log(\"100% done");
+        assert_eq!(
+            tokens[2].typ,
+            TokenType::Error(LexError::UnterminatedString)
+        );
+        assert_eq!((tokens[2].pos.start, tokens[2].pos.end), (6, 16));
+    }
 }
@@ -0,0 +1,779 @@
+use super::resolve::{resolve_target, state_at, suggestions};
+use super::tokenizer::Position;
+use super::tokenizer::{Token, TokenType};
+use super::{ParseError, StateNode, StateType, TransitionNode};
+use std::collections::HashSet;
+
+// absolute paths of all the states some transition goes to
+type Targets<'a> = HashSet<Vec<&'a str>>;
+
+// The parser only checks that the sketch is well formed. A well formed sketch
+// can still describe a statechart which xstate would reject, or would quietly
+// interpret differently from what the author meant. This pass walks the ast
+// after parsing and reports those mistakes.
+pub fn validate<'a>(ast: &StateNode<'a>) -> Vec<ParseError<'a>> {
+    let mut errors = vec![];
+    let mut targets = HashSet::new();
+    collect_targets(ast, ast, &mut vec![], &mut targets);
+    validate_state(ast, ast, &targets, &mut vec![], true, &mut errors);
+    errors
+}
+
+fn collect_targets<'a>(
+    root: &StateNode<'a>,
+    state: &StateNode<'a>,
+    path: &mut Vec<&'a str>,
+    targets: &mut Targets<'a>,
+) {
+    path.push(state.id);
+
+    let history_target = state.target;
+    for target in state
+        .all_transitions()
+        .flat_map(|t| t.target.iter().copied())
+        .chain(history_target)
+    {
+        if let Ok(target_path) = resolve_target(root, path, target) {
+            targets.insert(target_path);
+        }
+    }
+
+    for child in state.states.values() {
+        collect_targets(root, child, path, targets);
+    }
+
+    path.pop();
+}
+
+// `path` is the absolute path of `state`'s parent. `check_targets` is false
+// inside a duplicate state. Its path leads to the first state with that name,
+// so its targets and the states above it would be looked up in the wrong
+// place. The duplicate is already an error.
+fn validate_state<'a>(
+    root: &StateNode<'a>,
+    state: &StateNode<'a>,
+    targets: &Targets<'a>,
+    path: &mut Vec<&'a str>,
+    check_targets: bool,
+    errors: &mut Vec<ParseError<'a>>,
+) {
+    if path.is_empty() {
+        if state.typ == StateType::History {
+            errors.push(ParseError::invalid(
+                format!("the root state `{}` can't be a history state", state.id),
+                state.pos.clone(),
+                vec![],
+            ));
+        }
+        if let Some(initial) = marker(state, TokenType::InitialState) {
+            errors.push(
+                ParseError::invalid(
+                    format!("`{}` is the root state, it is always initial", state.id),
+                    initial.pos.clone(),
+                    vec![],
+                )
+                .into_warning(),
+            );
+        }
+    }
+    path.push(state.id);
+
+    state_markers(state, errors);
+    history_state(state, errors);
+    initial_markers(state, errors);
+    duplicate_states(state, errors);
+    duplicate_transitions(state, errors);
+    unreachable_done(state, path, targets, errors);
+    if check_targets {
+        for transition in state.all_transitions() {
+            for (target, pos) in transition.target.iter().zip(&transition.target_pos) {
+                unknown_target(root, path, target, pos, errors);
+            }
+            orthogonal_targets(root, path, transition, errors);
+        }
+        shadowed_by_wildcard(root, state, path, errors);
+        if let Some(target) = state.target {
+            unknown_target(root, path, target, &state.target_pos, errors);
+        }
+    }
+
+    for child in state.states.values() {
+        validate_state(root, child, targets, path, check_targets, errors);
+    }
+    for duplicate in &state.duplicate_states {
+        validate_state(root, duplicate, targets, path, false, errors);
+    }
+
+    path.pop();
+}
+
+fn marker<'b, 'a>(state: &'b StateNode<'a>, typ: TokenType) -> Option<&'b Token<'a>> {
+    state.markers.iter().find(|t| t.typ == typ)
+}
+
+// `&` wins over `$` when both are given, and xstate ignores the children and
+// transitions of a final state. None of that is what the author meant.
+fn state_markers<'a>(state: &StateNode<'a>, errors: &mut Vec<ParseError<'a>>) {
+    let parallel = marker(state, TokenType::ParallelState);
+    let fin = marker(state, TokenType::FinalState);
+
+    if let (Some(parallel), Some(fin)) = (parallel, fin) {
+        errors.push(ParseError::invalid(
+            format!("state `{}` can't be both parallel and final", state.id),
+            fin.pos.clone(),
+            vec![parallel.pos.clone()],
+        ));
+    }
+
+    if state.typ != StateType::FinalState {
+        return;
+    }
+
+    if let Some(child) = state.states.values().next() {
+        errors.push(ParseError::invalid(
+            format!("final state `{}` can't have child states", state.id),
+            child.pos.clone(),
+            vec![state.pos.clone()],
+        ));
+    }
+
+    let first_transition = state.all_transitions().min_by_key(|t| t.pos.start);
+    if let Some(transition) = first_transition {
+        errors.push(ParseError::invalid(
+            format!("final state `{}` can't have transitions", state.id),
+            transition.pos.clone(),
+            vec![state.pos.clone()],
+        ));
+    }
+}
+
+// A history state only stands in for other states. It can't have anything
+// of its own, or be anything else at the same time.
+fn history_state<'a>(state: &StateNode<'a>, errors: &mut Vec<ParseError<'a>>) {
+    if state.typ != StateType::History {
+        return;
+    }
+
+    for token in &state.markers {
+        if let TokenType::History(_) = token.typ {
+            continue;
+        }
+        errors.push(ParseError::invalid(
+            format!("history state `{}` can't be marked {}", state.id, token.typ),
+            token.pos.clone(),
+            vec![],
+        ));
+    }
+
+    let first_line = state
+        .states
+        .values()
+        .map(|s| &s.pos)
+        .chain(state.all_transitions().map(|t| &t.pos))
+        .min_by_key(|pos| pos.start);
+    let has_actions = !(state.entry.is_empty()
+        && state.exit.is_empty()
+        && state.activities.is_empty()
+        && state.invoke.is_empty());
+
+    if first_line.is_some() || has_actions {
+        errors.push(ParseError::invalid(
+            format!(
+                "history state `{}` can't have child states, transitions or actions",
+                state.id
+            ),
+            first_line.unwrap_or(&state.pos).clone(),
+            vec![state.pos.clone()],
+        ));
+    }
+}
+
+// A compound state is done when it enters one of its final children. A
+// parallel state is done when all its regions are. `path` is the absolute path
+// of `state`.
+fn can_finish<'a>(state: &StateNode<'a>, path: &mut Vec<&'a str>, targets: &Targets<'a>) -> bool {
+    match state.typ {
+        StateType::FinalState => true,
+        StateType::CompoundState => state.states.values().any(|child| {
+            path.push(child.id);
+            let reachable = child.typ == StateType::FinalState
+                && (state.initial == Some(child.id) || targets.contains(path));
+            path.pop();
+            reachable
+        }),
+        StateType::ParallelState => state.states.values().all(|region| {
+            path.push(region.id);
+            let finishes = can_finish(region, path, targets);
+            path.pop();
+            finishes
+        }),
+        StateType::AtomicState | StateType::History => false,
+    }
+}
+
+// a `done` transition which can never be taken
+fn unreachable_done<'a>(
+    state: &StateNode<'a>,
+    path: &mut Vec<&'a str>,
+    targets: &Targets<'a>,
+    errors: &mut Vec<ParseError<'a>>,
+) {
+    let done = match state.on_done.first() {
+        Some(done) => done,
+        None => return,
+    };
+
+    if !can_finish(state, path, targets) {
+        errors.push(
+            ParseError::invalid(
+                format!(
+                    "`{}` never reaches a final state, so `done` is never taken",
+                    state.id
+                ),
+                done.pos.clone(),
+                vec![],
+            )
+            .into_warning(),
+        );
+    }
+}
+
+// Only one child can be the initial state. Parallel states don't have one at
+// all, every region is entered at once.
+fn initial_markers<'a>(state: &StateNode<'a>, errors: &mut Vec<ParseError<'a>>) {
+    let mut marked = state
+        .states
+        .values()
+        .filter_map(|child| marker(child, TokenType::InitialState).map(|m| (child, m)));
+
+    if state.typ == StateType::ParallelState {
+        for (child, initial) in marked {
+            errors.push(
+                ParseError::invalid(
+                    format!(
+                        "`{}` is a region of parallel state `{}`, it can't be marked initial",
+                        child.id, state.id
+                    ),
+                    initial.pos.clone(),
+                    vec![],
+                )
+                .into_warning(),
+            );
+        }
+        return;
+    }
+
+    if let Some((first, first_initial)) = marked.next() {
+        for (child, initial) in marked {
+            errors.push(ParseError::invalid(
+                format!(
+                    "`{}` and `{}` are both marked initial in `{}`",
+                    first.id, child.id, state.id
+                ),
+                initial.pos.clone(),
+                vec![first_initial.pos.clone()],
+            ));
+        }
+    }
+}
+
+// Sibling states share a namespace. Only the first of them makes it into the
+// output, so every later one is reported along with where the first one is.
+fn duplicate_states<'a>(state: &StateNode<'a>, errors: &mut Vec<ParseError<'a>>) {
+    for duplicate in &state.duplicate_states {
+        let first = &state.states[duplicate.id];
+
+        errors.push(ParseError::invalid(
+            format!(
+                "state `{}` is already declared in `{}`",
+                duplicate.id, state.id
+            ),
+            duplicate.pos.clone(),
+            vec![first.pos.clone()],
+        ));
+    }
+}
+
+// xstate takes the first transition for an event whose condition holds. A
+// transition without a condition always holds, so any later transition on the
+// same event without a condition can never be taken. Same for delays, `done`
+// and the `done` and `error` of each invoke.
+fn duplicate_transitions<'a>(state: &StateNode<'a>, errors: &mut Vec<ParseError<'a>>) {
+    let on: Vec<_> = state
+        .on
+        .iter()
+        .map(|t| (format!("event `{}`", t.event), t))
+        .collect();
+    let after: Vec<_> = state
+        .after
+        .iter()
+        .map(|t| (format!("`after {}`", t.delay.as_ref().unwrap().key()), t))
+        .collect();
+    let done: Vec<_> = state
+        .on_done
+        .iter()
+        .map(|t| ("`done`".to_string(), t))
+        .collect();
+    let invokes = state.invoke.iter().map(|invoke| {
+        let name = invoke.id.unwrap_or(invoke.src);
+        invoke
+            .on_done
+            .iter()
+            .chain(&invoke.on_error)
+            .map(|t| (format!("`{}` of invoke `{}`", t.event, name), t))
+            .collect::<Vec<_>>()
+    });
+
+    for transitions in vec![on, after, done].into_iter().chain(invokes) {
+        let unguarded: Vec<_> = transitions
+            .iter()
+            .filter(|(_, t)| t.cond.is_none())
+            .collect();
+
+        for (i, (trigger, transition)) in unguarded.iter().enumerate() {
+            if let Some((_, first)) = unguarded[..i].iter().find(|(t, _)| t == trigger) {
+                errors.push(ParseError::invalid(
+                    format!(
+                        "{} already has a transition without a condition in `{}`",
+                        trigger, state.id
+                    ),
+                    transition.pos.clone(),
+                    vec![first.pos.clone()],
+                ));
+            }
+        }
+    }
+}
+
+// An event goes to the deepest state first. A `*` transition without a
+// condition takes every event its own state has no transition for, so none
+// of them get up to the states above it. `path` is the absolute path of
+// `state`.
+fn shadowed_by_wildcard<'a>(
+    root: &StateNode<'a>,
+    state: &StateNode<'a>,
+    path: &[&'a str],
+    errors: &mut Vec<ParseError<'a>>,
+) {
+    let wildcard = match state.on.iter().find(|t| t.event == "*" && t.cond.is_none()) {
+        Some(wildcard) => wildcard,
+        None => return,
+    };
+
+    let mut reported = HashSet::new();
+    for ancestor in (1..path.len())
+        .rev()
+        .filter_map(|end| state_at(root, &path[..end]))
+    {
+        // transient transitions aren't triggered by events, so they are fine
+        for transition in ancestor.on.iter().filter(|t| {
+            t.event != "*"
+                && !t.event.is_empty()
+                && !state.on.iter().any(|own| own.event == t.event)
+        }) {
+            if !reported.insert(transition.event) {
+                continue;
+            }
+            errors.push(
+                ParseError::invalid(
+                    format!(
+                        "event `{}` in `{}` is never taken from `{}`, its `*` transition catches it first",
+                        transition.event, ancestor.id, state.id
+                    ),
+                    wildcard.pos.clone(),
+                    vec![transition.pos.clone()],
+                )
+                .into_warning(),
+            );
+        }
+    }
+}
+
+// Several targets are only allowed when each one is in a different region of
+// a parallel state. Otherwise xstate would have to be in two states of the
+// same compound state at once.
+fn orthogonal_targets<'a>(
+    root: &StateNode<'a>,
+    source: &[&'a str],
+    transition: &TransitionNode<'a>,
+    errors: &mut Vec<ParseError<'a>>,
+) {
+    // unknown targets are reported by unknown_target
+    let resolved: Vec<_> = transition
+        .target
+        .iter()
+        .zip(&transition.target_pos)
+        .filter_map(|(target, pos)| {
+            let path = resolve_target(root, source, target).ok()?;
+            Some((target, pos, path))
+        })
+        .collect();
+
+    for (i, (target, pos, path)) in resolved.iter().enumerate() {
+        let conflict = resolved[..i].iter().find(|(_, _, other)| {
+            // the states both targets are in
+            let common = path.iter().zip(other).take_while(|(a, b)| a == b).count();
+            common == path.len()
+                || common == other.len()
+                || !matches!(
+                    state_at(root, &path[..common]),
+                    Some(state) if state.typ == StateType::ParallelState
+                )
+        });
+
+        if let Some((other, other_pos, _)) = conflict {
+            errors.push(ParseError::invalid(
+                format!(
+                    "targets `{}` and `{}` are not in different regions of a parallel state",
+                    other, target
+                ),
+                (*pos).clone(),
+                vec![(*other_pos).clone()],
+            ));
+        }
+    }
+}
+
+fn unknown_target<'a>(
+    root: &StateNode<'a>,
+    source: &[&'a str],
+    target: &str,
+    target_pos: &Position,
+    errors: &mut Vec<ParseError<'a>>,
+) {
+    let unresolved = match resolve_target(root, source, target) {
+        Ok(_) => return,
+        Err(unresolved) => unresolved,
+    };
+
+    // suggest whole targets, with only the part which did not match replaced
+    let segments: Vec<&str> = target.split('.').collect();
+    let name = segments[unresolved.segment];
+    let (prefix, name) = match name.strip_prefix('#') {
+        Some(id) => ("#", id),
+        None => ("", name),
+    };
+    let suggested: Vec<String> = suggestions(name, &unresolved.candidates)
+        .into_iter()
+        .map(|candidate| {
+            let mut segments = segments.clone();
+            let replacement = format!("{}{}", prefix, candidate);
+            segments[unresolved.segment] = &replacement;
+            format!("`{}`", segments.join("."))
+        })
+        .collect();
+
+    let mut message = format!("unknown target `{}`", target);
+    if !suggested.is_empty() {
+        message.push_str(&format!(", did you mean {}?", suggested.join(" or ")));
+    }
+
+    // only a warning. While a sketch is being written, transitions often
+    // point at states which haven't been typed yet.
+    errors.push(ParseError::invalid(message, target_pos.clone(), vec![]).into_warning());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Parser;
+    use super::*;
+
+    fn errors(input: &str) -> Vec<ParseError<'_>> {
+        Parser::new().parse_with_recovery(input).errors
+    }
+
+    fn warnings(input: &str) -> Vec<ParseError<'_>> {
+        Parser::new().parse_with_recovery(input).warnings
+    }
+
+    #[test]
+    fn duplicate_sibling_states() {
+        let errors = errors("abc\n  def\n    ghi\n  jkl\n  def");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "state `def` is already declared in `abc`"
+        );
+        assert_eq!(errors[0].pos.line_number, 4);
+        assert_eq!(errors[0].related.len(), 1);
+        assert_eq!(errors[0].related[0].line_number, 1);
+    }
+
+    #[test]
+    fn same_name_in_different_parents_is_fine() {
+        assert_eq!(errors("abc\n  def\n    ghi\n  jkl\n    ghi"), vec![]);
+    }
+
+    #[test]
+    fn duplicate_unguarded_transitions() {
+        let errors =
+            errors("abc\n  GO -> def\n  GO -> ghi; ready\n  GO -> jkl\n  def\n  ghi\n  jkl");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "event `GO` already has a transition without a condition in `abc`"
+        );
+        assert_eq!(errors[0].pos.line_number, 3);
+        assert_eq!(errors[0].related[0].line_number, 1);
+    }
+
+    #[test]
+    fn guarded_transitions_on_the_same_event_are_fine() {
+        assert_eq!(
+            errors("abc\n  GO -> def; ready\n  GO -> abc\n  def"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn unknown_targets() {
+        let errors = warnings(
+            "abc
+  def
+    GO -> jkll
+    STOP -> #abd.def
+    BACK -> .nope
+  jkl",
+        );
+
+        let messages: Vec<&str> = errors.iter().map(|e| &e.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unknown target `jkll`, did you mean `jkl`?",
+                "unknown target `#abd.def`, did you mean `#abc.def`?",
+                "unknown target `.nope`",
+            ]
+        );
+        assert_eq!(errors[0].pos.line_number, 2);
+        assert_eq!(errors[0].pos.col, 10);
+        assert_eq!(errors[0].pos.end - errors[0].pos.start, 4);
+    }
+
+    #[test]
+    fn parallel_and_final() {
+        let errors = errors("abc\n  def&$\n  ghi$\n    jkl\n    after 1s -> jkl\n    GO -> jkl");
+        let found: Vec<(usize, usize, &str)> = errors
+            .iter()
+            .map(|e| (e.pos.line_number, e.pos.col, &e.message[..]))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (1, 6, "state `def` can't be both parallel and final"),
+                (3, 4, "final state `ghi` can't have child states"),
+                (4, 4, "final state `ghi` can't have transitions"),
+            ]
+        );
+        assert_eq!(errors[0].related[0].col, 5);
+    }
+
+    #[test]
+    fn several_initial_states() {
+        let errors = errors("abc\n  def*\n  ghi\n  jkl*");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "`def` and `jkl` are both marked initial in `abc`"
+        );
+        assert_eq!((errors[0].pos.line_number, errors[0].pos.col), (3, 5));
+        assert_eq!(errors[0].related[0].line_number, 1);
+    }
+
+    #[test]
+    fn initial_markers_that_have_no_effect() {
+        let output = Parser::new().parse_with_recovery("abc*\n  def&\n    ghi*\n    jkl");
+
+        assert_eq!(output.errors, vec![]);
+        let messages: Vec<&str> = output.warnings.iter().map(|e| &e.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`abc` is the root state, it is always initial",
+                "`ghi` is a region of parallel state `def`, it can't be marked initial",
+            ]
+        );
+    }
+
+    #[test]
+    fn history_states_stand_alone() {
+        let found = errors(
+            "abc
+  hist H*&
+  deep H
+    GO -> abc
+  def
+    inner H -> nope",
+        );
+        let found: Vec<(usize, &str)> = found
+            .iter()
+            .map(|e| (e.pos.line_number, &e.message[..]))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (1, "history state `hist` can't be marked `&`"),
+                (
+                    3,
+                    "history state `deep` can't have child states, transitions or actions"
+                ),
+            ]
+        );
+        assert_eq!(
+            warnings("abc\n  def\n    inner H -> nope")[0].message,
+            "unknown target `nope`"
+        );
+        assert_eq!(
+            errors("abc H")[0].message,
+            "the root state `abc` can't be a history state"
+        );
+    }
+
+    #[test]
+    fn duplicate_delays() {
+        let errors = errors("abc\n  after 1s -> abc\n  after 1000ms -> abc\n  after 2s -> abc");
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "`after 1000` already has a transition without a condition in `abc`"
+        );
+        assert_eq!(errors[0].pos.line_number, 2);
+    }
+
+    #[test]
+    fn duplicate_done_and_error_transitions() {
+        let errors = errors(
+            "abc
+  invoke fetchUser
+    done -> def
+    error -> ghi
+    error -> def
+    done -> ghi; isAdmin
+  invoke saveUser as save
+    done -> def
+  def
+  ghi$
+  done -> def
+  done -> ghi",
+        );
+
+        let messages: Vec<&str> = errors.iter().map(|e| &e.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`done` already has a transition without a condition in `abc`",
+                "`error` of invoke `fetchUser` already has a transition without a condition in `abc`",
+            ]
+        );
+        assert_eq!(errors[0].pos.line_number, 11);
+        assert_eq!(errors[0].related[0].line_number, 10);
+        assert_eq!(errors[1].pos.line_number, 4);
+        assert_eq!(errors[1].related[0].line_number, 3);
+    }
+
+    #[test]
+    fn done_needs_a_reachable_final_state() {
+        let warnings =
+            |input| -> Vec<String> { warnings(input).into_iter().map(|w| w.message).collect() };
+
+        // `sent` can be reached
+        assert_eq!(
+            warnings("app\n  form\n    editing\n      SUBMIT -> sent\n    sent$\n    done -> thanks\n  thanks"),
+            Vec::<String>::new()
+        );
+        // nothing goes to `sent`
+        assert_eq!(
+            warnings("app\n  form\n    editing\n    sent$\n    done -> thanks\n  thanks"),
+            vec!["`form` never reaches a final state, so `done` is never taken"]
+        );
+        // one region of the parallel state never finishes
+        assert_eq!(
+            warnings(
+                "app
+  upload&
+    file
+      sending
+        OK -> sent
+      sent$
+    thumbnail
+      rendering
+    done -> idle
+  idle"
+            ),
+            vec!["`upload` never reaches a final state, so `done` is never taken"]
+        );
+    }
+
+    #[test]
+    fn targets_in_parallel_regions() {
+        let sketch = |targets| {
+            format!(
+                "m
+  idle
+    GO -> {}
+  active&
+    a
+      x
+      y
+    b
+      z",
+                targets
+            )
+        };
+
+        assert_eq!(errors(&sketch("active.a.x, active.b.z")), vec![]);
+
+        let input = sketch("active.a.x, active.b.z, #y");
+        let conflicts = errors(&input);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].message,
+            "targets `active.a.x` and `#y` are not in different regions of a parallel state"
+        );
+        assert_eq!(conflicts[0].pos.col, 34);
+        assert_eq!(conflicts[0].related[0].col, 10);
+
+        let input = sketch("active, active.b");
+        assert_eq!(
+            errors(&input)[0].message,
+            "targets `active` and `active.b` are not in different regions of a parallel state"
+        );
+    }
+
+    #[test]
+    fn wildcard_shadowing_events_of_ancestors() {
+        let shadowed = warnings("m\n  a\n    * -> b\n    STOP -> b\n  b\n  FOO -> a\n  STOP -> b");
+
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(
+            shadowed[0].message,
+            "event `FOO` in `m` is never taken from `a`, its `*` transition catches it first"
+        );
+        assert_eq!(shadowed[0].pos.line_number, 2);
+        assert_eq!(shadowed[0].related[0].line_number, 5);
+
+        // a wildcard with a condition lets the events it doesn't take through
+        assert_eq!(
+            warnings("m\n  a\n    * -> b; isError\n  b\n  FOO -> a"),
+            vec![]
+        );
+        // and one in the root has nothing above it
+        assert_eq!(warnings("m\n  * -> a\n  GO -> a\n  a"), vec![]);
+    }
+
+    #[test]
+    fn targets_inside_duplicate_states_are_not_resolved() {
+        let output =
+            Parser::new().parse_with_recovery("abc\n  def\n  def\n    ghi\n      GO -> .x");
+
+        let messages: Vec<&str> = output.errors.iter().map(|e| &e.message[..]).collect();
+        assert_eq!(messages, vec!["state `def` is already declared in `abc`"]);
+        assert_eq!(output.warnings, vec![]);
+    }
+}